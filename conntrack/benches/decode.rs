@@ -0,0 +1,47 @@
+//! Compares reading the `FlowHeader` of dumped entries through `FlowRef`, as
+//! `Conntrack::dump_headers()` does, with decoding them into `Flow`s, as
+//! `Conntrack::dump()` does. The fixture is a dump of 256 TCP and UDP entries
+//! recorded on a little-endian host.
+
+use conntrack::model::Flow;
+use conntrack::view::FlowRef;
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+
+const DUMP: &[u8] = include_bytes!("fixtures/dump.bin");
+
+/// Splits the recorded dump into its netlink messages.
+fn messages() -> Vec<&'static [u8]> {
+    let mut messages = Vec::new();
+    let mut raw = DUMP;
+    while !raw.is_empty() {
+        let message = FlowRef::from_bytes(raw).unwrap().as_bytes();
+        messages.push(message);
+        raw = &raw[message.len().next_multiple_of(4).min(raw.len())..];
+    }
+    messages
+}
+
+fn decode(c: &mut Criterion) {
+    let messages = messages();
+    let mut group = c.benchmark_group("dump of 256 entries");
+
+    group.bench_function("FlowRef::header", |b| {
+        b.iter(|| {
+            for message in &messages {
+                black_box(FlowRef::from_bytes(message).unwrap().header());
+            }
+        })
+    });
+    group.bench_function("Flow::from_bytes", |b| {
+        b.iter(|| {
+            for message in &messages {
+                black_box(Flow::from_bytes(message).unwrap());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -11,12 +11,13 @@ fn main() -> Result<()> {
     env_logger::init_from_env(env);
 
     // Create the Conntrack table via netfilter socket syscall
-    let mut ct = Conntrack::connect()?;
+    let ct = Conntrack::connect()?;
 
     // Dump conntrack table as a Vec<Flow>
-    ct.delete(libc::IPPROTO_TCP as u8, ip, true)
-        .map_err(|e| error!("{e}"))
-        .ok();
+    let flows = ct.dump()?;
+    for flow in flows {
+        log::info!("{flow:?}");
+    }
 
     Ok(())
 }
@@ -0,0 +1,27 @@
+use conntrack::{model::*, *};
+use env_logger::Env;
+
+/// This example enables logging, connects to netfilter via socket subscribed to all
+/// conntrack event groups, and prints each event as it is received.
+fn main() -> Result<()> {
+    let env = Env::default()
+        .filter_or("RUST_LOG", "info")
+        .write_style_or("RUST_LOG_STYLE", "always");
+
+    env_logger::init_from_env(env);
+
+    // Create the Conntrack table via netfilter socket syscall, joining the NEW,
+    // UPDATE and DESTROY multicast groups
+    let ct = Conntrack::connect_with_groups(EventGroup::all())?;
+
+    // Block on the event stream, printing each event along with its flow
+    for event in ct.events()? {
+        match event {
+            Ok(event) => println!("{:?}: {:?}", event.event_type, event.flow),
+            Err(e) if e.is_transient() => log::warn!("{e}"),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
@@ -209,6 +209,13 @@ pub enum NatAttr {
     CtaNatv6MaxIp = 6u16,
 }
 
+#[neli_enum(serialized_type = "u16")]
+pub enum FilterAttr {
+    CtaFilterUnspec = 0u16,
+    CtaFilterOrigFlags = 1u16,
+    CtaFilterReplyFlags = 2u16,
+}
+
 #[neli_enum(serialized_type = "u16")]
 pub enum GlobalStatsAttr {
     CtaStatsGlobalUnspec = 0u16,
@@ -244,3 +251,4 @@ impl NlAttrType for SynProxyAttr {}
 impl NlAttrType for CpuStatsAttr {}
 impl NlAttrType for ExpectNatAttr {}
 impl NlAttrType for NatAttr {}
+impl NlAttrType for FilterAttr {}
@@ -2,132 +2,2179 @@
 //! This module contains the general API for the conntrack library.
 
 use neli::{
-    Size, ToBytes,
+    FromBytes, ToBytes,
     consts::{genl::NlAttrType, nl::*, socket::*},
-    genl::{AttrTypeBuilder, Genlmsghdr, GenlmsghdrBuilder, Nlattr, NlattrBuilder},
+    genl::{Genlmsghdr, GenlmsghdrBuilder},
+    nl::NlmsghdrBuilder,
     nl::{NlPayload, Nlmsghdr},
     router::synchronous::{NlRouter, NlRouterReceiverHandle},
+    socket::{NlSocket, synchronous::NlSocketHandle},
     types::{Buffer, GenlBuffer},
     utils::Groups,
 };
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Cursor};
 use std::net::IpAddr;
+use std::ops::ControlFlow;
+use std::ops::Range;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
 
 use crate::attributes::*;
 use crate::decoders::*;
+use crate::encoders::*;
 use crate::message::*;
 use crate::model::*;
 use crate::result::*;
+use crate::table::*;
+use crate::view::*;
+
+const RETRY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// The number of times a socket call interrupted by a signal, i.e. failing with
+/// `EINTR`, is retried before the error is returned.
+const EINTR_RETRIES: u32 = 8;
+
+/// The number of create requests `Conntrack::create_many()` sends ahead of their
+/// acknowledgements. Larger windows risk overrunning the socket receive buffer.
+const CREATE_WINDOW: usize = 32;
+
+/// The number of requests `Conntrack::delete_many()` and `Batch::commit()` send in
+/// a single datagram. The kernel answers each failing request with an error message echoing
+/// it, which must fit into the socket receive buffer along with the others.
+const BATCH_SIZE: usize = 64;
+
+/// The size of the buffer dump messages are received into, large enough for the multipart batches the kernel sends.
+const DUMP_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The size of the buffer `Conntrack::try_recv_event()` receives event datagrams
+/// into. The kernel sends a single event per datagram, which fits easily.
+const EVENT_BUFFER_SIZE: usize = 16 * 1024;
+
+/// The size of the connlabel bitmap in the kernel, `NF_CT_LABELS_MAX_SIZE`, which
+/// is updated in 32 bit words.
+const LABELS_MAX_SIZE: usize = 16;
+const LABELS_WORD_SIZE: usize = 4;
 
 /// The `Conntrack` type is used to connect to a netfilter socket and execute
 /// conntrack table specific commands.
+///
+/// `Conntrack` is `Send` and `Sync`, so a single instance can be shared between
+/// threads with an `Arc`. Requests from different threads are matched to their
+/// responses by sequence number, and dumps, which the kernel runs one at a time
+/// per socket, wait for each other. Events are not duplicated: threads iterating
+/// over `events()` of the same instance each receive a share of them.
+///
+/// Dropping a `Conntrack` closes all of its sockets and stops the thread receiving
+/// responses, logging a warning if it can't be woken up. Use `close()` to get
+/// that error instead. The thread of `monitor_channel()` owns a socket of its own
+/// and keeps running until it fails to forward an event.
+///
+/// ```no_run
+/// use conntrack::Conntrack;
+/// use std::{sync::Arc, thread};
+///
+/// let ct = Arc::new(Conntrack::connect()?);
+/// let workers: Vec<_> = (0..2)
+///     .map(|_| {
+///         let ct = Arc::clone(&ct);
+///         thread::spawn(move || ct.dump().map(|flows| flows.len()))
+///     })
+///     .collect();
+/// for worker in workers {
+///     println!("{} flows", worker.join().unwrap()?);
+/// }
+/// # Ok::<(), conntrack::Error>(())
+/// ```
 pub struct Conntrack {
-    socket: NlRouter,
+    socket: Router,
+    dump_socket: Mutex<DumpSocket>,
+    events: Option<NlSocketHandle>,
+    pending_events: Mutex<VecDeque<Result<Event>>>,
+    groups: EventGroup,
 }
 
 impl Conntrack {
     /// This method opens a netfilter socket using a `socket()` syscall, and
     /// returns the `Conntrack` instance on success.
     pub fn connect() -> Result<Self> {
-        let socket = NlRouter::connect(NlFamily::Netfilter, Some(0), Groups::empty())?.0;
-        Ok(Self { socket })
+        Self::connect_with_pid(0)
+    }
+
+    /// This method opens a netfilter socket like `connect()`, but binds it to the
+    /// given netlink port id instead of letting the kernel assign one. A `pid` of 0
+    /// falls back to the kernel assigned port id. Fails if the port id is already
+    /// taken by another netlink socket of the same family.
+    pub fn connect_with_pid(pid: u32) -> Result<Self> {
+        let socket = NlRouter::connect(NlFamily::Netfilter, Some(pid), Groups::empty())?.0;
+        let dump_socket = NlSocket::connect(NlFamily::Netfilter, Some(0), Groups::empty())?;
+        Ok(Self {
+            socket: Router {
+                router: Some(socket),
+                netns: None,
+            },
+            dump_socket: Mutex::new(DumpSocket {
+                socket: dump_socket,
+                seq: 0,
+                unfinished: None,
+            }),
+            events: None,
+            pending_events: Mutex::new(VecDeque::new()),
+            groups: EventGroup::empty(),
+        })
+    }
+
+    /// This method opens a netfilter socket like `connect()`, but inside the network
+    /// namespace at `path`, e.g. `/var/run/netns/tenant`. The namespace is entered
+    /// by a short-lived helper thread opening the sockets, so the calling thread
+    /// never leaves its own namespace, even when entering the namespace or opening
+    /// the sockets fails. Every request
+    /// sent through the returned instance then operates on the conntrack table of
+    /// that namespace, and the event sockets it opens later, e.g. with
+    /// `subscribe()` or `watch()`, receive the events of that namespace. Requires
+    /// `CAP_SYS_ADMIN`.
+    pub fn connect_in_netns(path: &Path) -> Result<Self> {
+        Self::connect_in_netns_fd(File::open(path)?)
+    }
+
+    /// This method behaves like `connect_in_netns()`, but takes an already opened
+    /// namespace file descriptor, e.g. one received from a container runtime or
+    /// opened from `/proc/<pid>/ns/net`. The instance keeps a duplicate of the
+    /// descriptor to open its later sockets in the same namespace, so the given
+    /// one may be closed once the instance is returned.
+    pub fn connect_in_netns_fd(ns: impl AsFd) -> Result<Self> {
+        let ns = ns.as_fd();
+        let mut conntrack = in_netns(Some(ns), Self::connect)?;
+        conntrack.socket.netns = Some(ns.try_clone_to_owned()?);
+
+        Ok(conntrack)
+    }
+
+    /// This method opens a netfilter socket like `connect()`, and additionally
+    /// opens an event socket subscribed to the given multicast `groups`. Only the
+    /// requested event types are delivered, e.g. `EventGroup::DESTROY` alone
+    /// receives connection teardowns along with their final counters.
+    pub fn connect_with_groups(groups: EventGroup) -> Result<Self> {
+        let mut conntrack = Self::connect()?;
+        conntrack.subscribe(groups)?;
+
+        Ok(conntrack)
+    }
+
+    /// The subscribe call opens the event socket of an instance like
+    /// `connect_with_groups()` does, replacing the one of an earlier subscription,
+    /// e.g. to receive the events of an instance from `connect_in_netns()`. Events
+    /// pending on the replaced socket are dropped.
+    pub fn subscribe(&mut self, groups: EventGroup) -> Result<()> {
+        self.events = Some(self.netns_event_socket(groups)?);
+        self.groups = groups;
+        self.pending_events
+            .get_mut()
+            .map_err(|_| Error::Netlink("event buffer lock poisoned".to_string()))?
+            .clear();
+
+        Ok(())
+    }
+
+    /// This method opens a netfilter socket like `connect()`, with a receive timeout
+    /// set like `set_timeout()`.
+    pub fn connect_with_timeout(timeout: Duration) -> Result<Self> {
+        let conntrack = Self::connect()?;
+        conntrack.set_timeout(Some(timeout))?;
+
+        Ok(conntrack)
+    }
+
+    /// The close call closes the sockets of the instance and stops the thread
+    /// receiving responses, like dropping it does, but returns the error if the
+    /// thread can't be woken up. The thread then stays blocked until its socket
+    /// receives a message.
+    pub fn close(mut self) -> Result<()> {
+        self.socket.close()
+    }
+
+    /// The set_timeout call limits how long dumps and the `events()` iterator wait
+    /// for the kernel, by setting `SO_RCVTIMEO` on their sockets. A receive that
+    /// times out fails with `Error::Timeout`, which is transient, so the events
+    /// iterator can be polled again afterwards. `None` waits forever, which is the
+    /// default. Requests acknowledged by the kernel, e.g. `create()`, and the event
+    /// socket of `monitor_channel()` are not affected.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        let dump_socket = self
+            .dump_socket
+            .lock()
+            .map_err(|_| Error::Netlink("dump socket lock poisoned".to_string()))?;
+        set_recv_timeout(&dump_socket.socket, timeout)?;
+        if let Some(events) = &self.events {
+            set_recv_timeout(events, timeout)?;
+        }
+
+        Ok(())
+    }
+
+    /// The event_fd call returns the file descriptor of the event socket subscribed
+    /// with `connect_with_groups()`, so it can be registered with a reactor like
+    /// epoll or mio. Once it is readable, `try_recv_event()` receives the pending
+    /// events without blocking. Requests and dumps use sockets of their own, so
+    /// their replies never make the descriptor readable.
+    pub fn event_fd(&self) -> Result<BorrowedFd<'_>> {
+        let socket = self.events.as_ref().ok_or(Error::NotSubscribed)?;
+
+        // SAFETY: the socket stays open as long as `self` is borrowed.
+        Ok(unsafe { BorrowedFd::borrow_raw(socket.as_raw_fd()) })
+    }
+
+    /// The try_recv_event call returns the next conntrack event of the groups
+    /// subscribed with `connect_with_groups()` if one is available, or `None`
+    /// without blocking otherwise. It is meant for custom event loops polling
+    /// `event_fd()`. Events of a datagram beyond the first are kept for the next
+    /// calls, so keep calling until it returns `None` before waiting again.
+    pub fn try_recv_event(&self) -> Result<Option<Event>> {
+        let socket = self.events.as_ref().ok_or(Error::NotSubscribed)?;
+        let mut pending = self
+            .pending_events
+            .lock()
+            .map_err(|_| Error::Netlink("event buffer lock poisoned".to_string()))?;
+
+        let mut buffer = Vec::new();
+        while pending.is_empty() {
+            buffer.resize(EVENT_BUFFER_SIZE, 0);
+            let Some((len, group)) = try_recv(socket, &mut buffer)? else {
+                return Ok(None);
+            };
+            if !self.groups.intersects(group) {
+                continue;
+            }
+            for message in messages(&buffer[..len]) {
+                pending.push_back(message.and_then(|message| {
+                    let message = Nlmsghdr::from_bytes(&mut Cursor::new(message.as_bytes()))?;
+                    decode_event(message, group)
+                }));
+            }
+        }
+
+        pending.pop_front().transpose()
+    }
+
+    /// The events call returns a blocking iterator over the conntrack events of
+    /// the groups subscribed with `connect_with_groups()`.
+    pub fn events(&self) -> Result<Events<'_>> {
+        self.events_of(self.groups)
+    }
+
+    /// The events_of call behaves like `events()`, but only yields the events of
+    /// the given `types`. Messages of other subscribed groups are discarded before
+    /// they are decoded, which keeps a broad subscription cheap for consumers that
+    /// only need some of its events.
+    pub fn events_of(&self, types: EventGroup) -> Result<Events<'_>> {
+        let socket = self.events.as_ref().ok_or(Error::NotSubscribed)?;
+
+        Ok(Events {
+            socket,
+            types,
+            tuple: None,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// The events_matching call behaves like `events()`, but only yields the events
+    /// whose origin or reply tuple matches `tuple`, see `IpTuple::matches()`. The
+    /// kernel delivers every event of the subscribed groups, so the others are
+    /// decoded and dropped by the iterator. For example, a tuple with only `src`
+    /// set yields the events of the connections from and to that address, as the
+    /// address is the source of one of their directions.
+    pub fn events_matching(&self, tuple: IpTuple) -> Result<Events<'_>> {
+        Ok(Events {
+            tuple: Some(tuple),
+            ..self.events()?
+        })
+    }
+
+    /// The monitor_channel call spawns a background thread receiving the conntrack
+    /// events of the groups subscribed with `connect_with_groups()`, and forwards
+    /// them over the returned channel. The thread owns a dedicated event socket, so
+    /// it doesn't compete with `events()` for messages, opened in the namespace the
+    /// instance was connected to. Transient errors such as
+    /// overruns are forwarded and the thread keeps receiving; other errors are
+    /// forwarded before the thread stops. Once the receiver is dropped, the thread
+    /// stops when it fails to forward the next event.
+    pub fn monitor_channel(&self) -> Result<Receiver<Result<Event>>> {
+        if self.events.is_none() {
+            return Err(Error::NotSubscribed);
+        }
+        let groups = self.groups;
+        let socket = self.netns_event_socket(groups)?;
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let events = Events {
+                socket: &socket,
+                types: groups,
+                tuple: None,
+                pending: VecDeque::new(),
+            };
+            for event in events {
+                let fatal = event.as_ref().is_err_and(|e| !e.is_transient());
+                if sender.send(event).is_err() || fatal {
+                    break;
+                }
+            }
+        });
+
+        Ok(receiver)
+    }
+
+    /// The monitor_with call receives the conntrack events of the groups subscribed
+    /// with `connect_with_groups()` on the calling thread, and hands each of them to
+    /// `f` until it returns `ControlFlow::Break`. Errors are handed to `f` as well;
+    /// after a receive error that isn't transient, e.g. the socket being closed, the
+    /// loop ends and the error is returned.
+    pub fn monitor_with<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(Result<Event>) -> ControlFlow<()>,
+    {
+        for event in self.events()? {
+            let fatal = match &event {
+                Err(e) if e.errno().is_some() && !e.is_transient() => e.errno(),
+                _ => None,
+            };
+            if f(event).is_break() {
+                break;
+            }
+            if let Some(errno) = fatal {
+                return Err(Error::IO(std::io::Error::from_raw_os_error(errno)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The watch call returns a `Watcher` keeping a `FlowTable` of the whole
+    /// `Conntrack` table up to date. The event socket is subscribed before the
+    /// initial dump, so changes made while dumping are applied afterwards rather
+    /// than lost. It is independent of the groups passed to `connect_with_groups()`.
+    /// The event socket is opened in the namespace the instance was connected to,
+    /// so the events match the dumped table.
+    pub fn watch(&self) -> Result<Watcher<'_>> {
+        let socket = self.netns_event_socket(EventGroup::all())?;
+        let table = self.dump()?.into_iter().collect();
+
+        Ok(Watcher {
+            conntrack: self,
+            socket,
+            pending: VecDeque::new(),
+            table,
+        })
+    }
+
+    /// The watch_and_dump call subscribes a new event socket to every event group in
+    /// the namespace the instance was connected to, then dumps the table, and returns the dumped flows along with the events
+    /// received from the moment of the subscription. Unlike `watch()`, the events
+    /// are not applied to a table, which is left to the caller.
+    ///
+    /// Every change made after the subscription is reported by an event, so there
+    /// is no gap between the snapshot and the events. The events of changes made
+    /// during the dump overlap with it, though: the dump may or may not already
+    /// reflect them. Applying the events in order on top of the snapshot, keyed by
+    /// flow id, gives the right result either way, as a `New` or `Update` event
+    /// replaces the dumped flow and a `Destroy` event removes a flow whether it was
+    /// dumped or not. The events of a large table changing quickly can overrun the
+    /// socket while dumping, in which case the stream yields `Error::Overrun` and a
+    /// new snapshot is needed.
+    ///
+    /// With the default `net.netfilter.nf_conntrack_events=2`, the kernel only sets
+    /// up events for entries created while some socket is subscribed. Entries that
+    /// were created before that are dumped, but their updates and destruction are
+    /// never reported, so they stay in a table built this way until the next
+    /// snapshot. The same applies to `watch()`. Setting the sysctl to 1 enables
+    /// events for every entry.
+    pub fn watch_and_dump(&self) -> Result<(Vec<Flow>, EventStream)> {
+        let socket = self.netns_event_socket(EventGroup::all())?;
+        let flows = self.dump()?;
+
+        Ok((
+            flows,
+            EventStream {
+                socket,
+                pending: VecDeque::new(),
+            },
+        ))
+    }
+
+    /// Opens an event socket subscribed to `groups` in the network namespace the
+    /// instance was connected to, see `connect_in_netns()`.
+    fn netns_event_socket(&self, groups: EventGroup) -> Result<NlSocketHandle> {
+        in_netns(self.socket.netns.as_ref().map(AsFd::as_fd), || {
+            event_socket(groups)
+        })
     }
 
     /// The dump call will list all connection tracking for the `Conntrack` table as a
     /// `Vec<Flow>` instances.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(command = "dump", family = libc::AF_UNSPEC, replies)
+        )
+    )]
     pub fn dump(&self) -> Result<Vec<Flow>> {
-        let genlhdr = GenlmsghdrBuilder::default()
-            .cmd(0u8)
-            .version(libc::NFNETLINK_V0 as u8)
-            .attrs(GenlBuffer::<ConntrackAttr, Buffer>::new())
-            .build()?;
+        let flows: Vec<Flow> = self.dump_iter()?.collect::<Result<_>>()?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("replies", flows.len());
 
-        let recv_iter = self.socket.send(
-            CtNetlinkMessage::Conntrack,
-            NlmF::DUMP,
-            NlPayload::Payload(genlhdr),
-        )?;
+        Ok(flows)
+    }
 
-        let mut flows = Vec::new();
-        for result in recv_iter {
-            let result: Nlmsghdr<CtNetlinkMessage, Genlmsghdr<u8, ConntrackAttr>> = result?;
-            if let NlPayload::Payload(message) = result.nl_payload() {
-                let handle = message.attrs().get_attr_handle();
+    /// The dump_with_retry call behaves like `dump()`, but retries the dump up to
+    /// `attempts` times with a linear backoff when it fails with a transient error
+    /// (see `Error::is_transient()`), including `Error::DumpInterrupted`. The last
+    /// error is returned otherwise.
+    pub fn dump_with_retry(&self, attempts: u32) -> Result<Vec<Flow>> {
+        let mut attempt = 1;
+        loop {
+            match self.dump() {
+                Err(e) if e.is_transient() && attempt < attempts => {
+                    log::debug!("retrying dump after transient error: {e}");
+                    thread::sleep(RETRY_BACKOFF * attempt);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// The dump_iter call lists the `Conntrack` table as a lazy iterator, decoding
+    /// each `Flow` only as the corresponding netlink message is consumed.
+    pub fn dump_iter(&self) -> Result<DumpIter<'_>> {
+        self.dump_filtered_iter(&DumpFilter::default())
+    }
+
+    /// The dump_filtered call lists the flows of the `Conntrack` table matching
+    /// `filter`. Unlike `dump_where()`, the filter is applied by the kernel, so
+    /// flows that don't match are never transferred.
+    pub fn dump_filtered(&self, filter: &DumpFilter) -> Result<Vec<Flow>> {
+        self.dump_filtered_iter(filter)?.collect()
+    }
+
+    /// The dump_filtered_iter call behaves like `dump_filtered()`, but returns a
+    /// lazy iterator like `dump_iter()`.
+    pub fn dump_filtered_iter(&self, filter: &DumpFilter) -> Result<DumpIter<'_>> {
+        Ok(DumpIter {
+            dump: Dump::new(self, filter, CtNetlinkMessage::Conntrack)?,
+        })
+    }
+
+    /// The dump_each call dumps the flows of the `Conntrack` table matching `filter`
+    /// and hands each of them to `f` as a `FlowRef`, until `f` returns
+    /// `ControlFlow::Break`. The views borrow the receive buffer, so no `Flow` is
+    /// decoded or allocated unless `FlowRef::to_owned()` is called, which makes this
+    /// the cheapest way to scan large tables.
+    pub fn dump_each<F>(&self, filter: &DumpFilter, mut f: F) -> Result<()>
+    where
+        F: FnMut(FlowRef<'_>) -> ControlFlow<()>,
+    {
+        let mut dump = Dump::new(self, filter, CtNetlinkMessage::Conntrack)?;
+        while let Some(message) = dump.next_message() {
+            if f(message?).is_break() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The dump_headers call lists the `Conntrack` table as a lazy iterator of
+    /// `FlowHeader`s, which only carry the protocol, status and TCP state of each
+    /// entry. The rest of each message is skipped instead of decoded, which makes
+    /// counting entries by state cheaper than a full `dump()`, as measured by
+    /// `cargo bench --bench decode`.
+    pub fn dump_headers(&self) -> Result<DumpHeaders<'_>> {
+        Ok(DumpHeaders {
+            dump: Dump::new(self, &DumpFilter::default(), CtNetlinkMessage::Conntrack)?,
+        })
+    }
+
+    /// The summary call counts the entries of the `Conntrack` table per protocol and
+    /// TCP state. It reads the entries with `dump_headers()`, so no `Flow` is built.
+    pub fn summary(&self) -> Result<Summary> {
+        let mut summary = Summary::default();
+        for header in self.dump_headers()? {
+            let header = header?;
+            summary.add(header.proto, header.tcp_state);
+        }
+
+        Ok(summary)
+    }
+
+    /// The dump_sorted call lists the `Conntrack` table like `dump()`, sorted by the
+    /// field selected with `by`, see `sort_flows()`.
+    pub fn dump_sorted(&self, by: SortKey) -> Result<Vec<Flow>> {
+        let mut flows = self.dump()?;
+        sort_flows(&mut flows, by);
+
+        Ok(flows)
+    }
+
+    /// The dump_reset_counters call lists the `Conntrack` table like `dump()`, and
+    /// has the kernel zero the counters of each entry as it is dumped. Each entry's
+    /// counters are read and cleared in a single atomic exchange, so the returned
+    /// flows carry exactly the traffic since the previous reset: exporting them and
+    /// then calling this again neither loses nor double counts bytes of entries that
+    /// live through both calls. The table as a whole isn't frozen, though. Entries
+    /// created during the dump may be missed until the next call, and the traffic
+    /// of entries destroyed between two calls is only reported by their destroy
+    /// events.
+    pub fn dump_reset_counters(&self) -> Result<Vec<Flow>> {
+        DumpIter {
+            dump: Dump::new(self, &DumpFilter::default(), CtNetlinkMessage::CtGetCtrZero)?,
+        }
+        .collect()
+    }
 
-                flows.push(Flow::decode(handle)?);
+    /// The reset_all_counters call zeros the counters of every entry of the
+    /// `Conntrack` table and returns the number of entries reset. The counters are
+    /// discarded without being decoded; use `dump_reset_counters()` to export them
+    /// with the guarantees described there.
+    pub fn reset_all_counters(&self) -> Result<u32> {
+        let mut dump = Dump::new(self, &DumpFilter::default(), CtNetlinkMessage::CtGetCtrZero)?;
+        let mut reset = 0;
+        while let Some(message) = dump.next_message() {
+            message?;
+            reset += 1;
+        }
+
+        Ok(reset)
+    }
+
+    /// The dump_where call lists the `Conntrack` table, keeping only the flows for
+    /// which `pred` returns `true`. Flows are decoded one at a time, so rejected
+    /// flows are dropped before the next message is read.
+    pub fn dump_where<F>(&self, mut pred: F) -> Result<Vec<Flow>>
+    where
+        F: FnMut(&Flow) -> bool,
+    {
+        let mut flows = Vec::new();
+        for flow in self.dump_iter()? {
+            let flow = flow?;
+            if pred(&flow) {
+                flows.push(flow);
             }
         }
 
         Ok(flows)
     }
 
-    pub fn delete(&self, proto: u8, ip: &IpAddr, src: bool) -> Result<()> {
-        let (top_attr_type, attr_type, bin) = match ip {
-            IpAddr::V4(ipv4) => {
-                let bin = ipv4.octets().to_vec();
-                if src {
-                    (ConntrackAttr::CtaTupleOrig, IpTupleAttr::CtaIpv4Src, bin)
-                } else {
-                    (ConntrackAttr::CtaTupleReply, IpTupleAttr::CtaIpv4Dst, bin)
-                }
+    /// The collect_metrics call dumps the `Conntrack` table and counts its entries
+    /// per protocol and TCP state, along with the maximum table size reported by
+    /// the kernel. See `ConntrackMetrics::to_prometheus()` for exporting them.
+    #[cfg(feature = "metrics")]
+    pub fn collect_metrics(&self) -> Result<crate::metrics::ConntrackMetrics> {
+        Ok(crate::metrics::ConntrackMetrics {
+            summary: self.summary()?,
+            max_entries: self.global_stats()?.max_entries,
+        })
+    }
+
+    /// The global_stats call requests the number of entries in the `Conntrack`
+    /// table and its maximum size, without dumping the table or reading the
+    /// sysctls. See `GlobalStats::utilization()` for how full the table is.
+    pub fn global_stats(&self) -> Result<GlobalStats> {
+        let genlhdr = nfgenmsg::<GlobalStatsAttr>(libc::AF_UNSPEC as u8, GenlBuffer::new())?;
+
+        let recv: NlRouterReceiverHandle<CtNetlinkMessage, Genlmsghdr<u8, GlobalStatsAttr>> =
+            self.socket.send(
+                CtNetlinkMessage::CtGetStats,
+                NlmF::ACK,
+                NlPayload::Payload(genlhdr),
+            )?;
+
+        let mut stats = None;
+        for result in recv {
+            if let NlPayload::Payload(message) = result?.nl_payload() {
+                stats = Some(GlobalStats::decode(message.attrs().get_attr_handle())?);
+            }
+        }
+
+        stats.ok_or_else(|| Error::Netlink("no statistics in stats response".to_string()))
+    }
+
+    /// The create call injects a new conntrack entry built with a `FlowBuilder`.
+    /// Fails with `Error::AlreadyExists` if an entry with the same tuple exists.
+    pub fn create(&self, flow: &NewFlow) -> Result<()> {
+        wait_ack(self.send_create(flow)?)
+    }
+
+    /// The create_many call injects all `flows` like `create()`, but keeps up to
+    /// `CREATE_WINDOW` requests in flight instead of waiting for each
+    /// acknowledgement in turn. The outcome of each entry is returned in the order of
+    /// `flows`, so a failing entry, e.g. one that already exists or has an invalid
+    /// tuple, doesn't prevent the others from being created.
+    pub fn create_many(&self, flows: &[NewFlow]) -> Result<Vec<Result<()>>> {
+        let mut results = Vec::with_capacity(flows.len());
+        let mut in_flight: VecDeque<Result<_>> = VecDeque::with_capacity(CREATE_WINDOW);
+
+        for flow in flows {
+            if in_flight.len() == CREATE_WINDOW
+                && let Some(recv) = in_flight.pop_front()
+            {
+                results.push(recv.and_then(wait_ack));
             }
-            IpAddr::V6(ipv6) => {
-                let bin = ipv6.octets().to_vec();
-                if src {
-                    (ConntrackAttr::CtaTupleOrig, IpTupleAttr::CtaIpv6Src, bin)
+            in_flight.push_back(self.send_create(flow));
+        }
+        results.extend(in_flight.into_iter().map(|recv| recv.and_then(wait_ack)));
+
+        Ok(results)
+    }
+
+    fn send_create(&self, flow: &NewFlow) -> Result<NlRouterReceiverHandle<u16, Buffer>> {
+        let genlhdr = nfgenmsg(family(&flow.origin.src), flow.encode()?)?;
+
+        Ok(self.socket.send(
+            CtNetlinkMessage::CtNew,
+            NlmF::CREATE | NlmF::EXCL | NlmF::ACK,
+            NlPayload::Payload(genlhdr),
+        )?)
+    }
+
+    /// The create_expectation call injects an expectation built with an
+    /// `ExpectationBuilder`, reserving the connection it predicts the way a helper
+    /// does. The master entry must exist and have a helper assigned, e.g. by a
+    /// `CT` rule or with the `helper` of the expectation, otherwise the kernel
+    /// rejects the request with `EOPNOTSUPP`. Fails with `Error::AlreadyExists` if
+    /// an expectation with the same tuple exists.
+    pub fn create_expectation(&self, exp: &NewExpectation) -> Result<()> {
+        let genlhdr = nfgenmsg(family(&exp.master.src), exp.encode()?)?;
+
+        wait_ack(self.socket.send(
+            CtNetlinkMessageExp::ExpNew,
+            NlmF::CREATE | NlmF::EXCL | NlmF::ACK,
+            NlPayload::Payload(genlhdr),
+        )?)
+    }
+
+    /// The get call looks up the conntrack entry matching the given origin `tuple`.
+    /// Returns `None` when the kernel has no such entry, whether it reports that as
+    /// `ENOENT` or with an acknowledgement alone; other failures are returned as
+    /// errors.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(command = "get", family = family(&tuple.src), replies)
+        )
+    )]
+    pub fn get(&self, tuple: &Tuple) -> Result<Option<Flow>> {
+        let mut attrs = GenlBuffer::<ConntrackAttr, Buffer>::new();
+        attrs.push(tuple.encode(Direction::Original.tuple_attr())?);
+
+        let genlhdr = nfgenmsg(family(&tuple.src), attrs)?;
+
+        let recv: NlRouterReceiverHandle<CtNetlinkMessage, Genlmsghdr<u8, ConntrackAttr>> =
+            self.socket.send(
+                CtNetlinkMessage::Conntrack,
+                NlmF::ACK,
+                NlPayload::Payload(genlhdr),
+            )?;
+
+        let mut flow = None;
+        for result in recv {
+            let message = match result {
+                Ok(message) => message,
+                Err(e) => match Error::from(e) {
+                    e if e.errno() == Some(libc::ENOENT) => return Ok(None),
+                    e => return Err(e),
+                },
+            };
+            if let NlPayload::Payload(message) = message.nl_payload() {
+                flow = Some(Flow::decode_message(message)?);
+            }
+        }
+
+        log::debug!(
+            "get for family {} returned {} flows",
+            family(&tuple.src),
+            usize::from(flow.is_some())
+        );
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("replies", usize::from(flow.is_some()));
+
+        Ok(flow)
+    }
+
+    /// The get_by_id call looks up the conntrack entry with the given `id`, as
+    /// reported in `Flow::id`. Returns `None` when no entry has this id, e.g.
+    /// because it was destroyed. The kernel only looks entries up by tuple (it
+    /// merely checks `CTA_ID` against the entry found), so the table is scanned
+    /// with `dump_each()` instead, and only the matching entry is decoded. Prefer
+    /// `get()` when the tuple is known.
+    pub fn get_by_id(&self, id: u32) -> Result<Option<Flow>> {
+        let mut found = None;
+        self.dump_each(&DumpFilter::default(), |flow| {
+            if flow.id() != Some(id) {
+                return ControlFlow::Continue(());
+            }
+            found = Some(flow.to_owned());
+            ControlFlow::Break(())
+        })?;
+
+        found.transpose()
+    }
+
+    /// The set_labels call updates the connlabels of the existing conntrack entry
+    /// matching the given origin `tuple`. Only the bits set in `mask` are replaced
+    /// by the corresponding bits of `labels`, the others keep their current value.
+    /// Both are padded with zeros to the kernel's 32 bit label words, so `labels`
+    /// and `mask` must have the same length, up to 16 bytes. The kernel rejects
+    /// the update with `ENOSPC` unless the connlabel extension is in use, e.g. by
+    /// an iptables `connlabel` rule.
+    pub fn set_labels(&self, tuple: &Tuple, labels: &[u8], mask: &[u8]) -> Result<()> {
+        if labels.len() != mask.len() {
+            return Err(Error::InvalidLabels("labels and mask lengths differ"));
+        }
+        if labels.is_empty() || labels.len() > LABELS_MAX_SIZE {
+            return Err(Error::InvalidLabels(
+                "length must be between 1 and 16 bytes",
+            ));
+        }
+
+        let padded = |bytes: &[u8]| {
+            let mut bytes = bytes.to_vec();
+            bytes.resize(bytes.len().next_multiple_of(LABELS_WORD_SIZE), 0);
+            Buffer::from(bytes)
+        };
+
+        let mut attrs = GenlBuffer::<ConntrackAttr, Buffer>::new();
+        attrs.push(tuple.encode(Direction::Original.tuple_attr())?);
+        attrs.push(make_attr(ConntrackAttr::CtaLabels, false, padded(labels))?);
+        attrs.push(make_attr(
+            ConntrackAttr::CtaLabelsMask,
+            false,
+            padded(mask),
+        )?);
+
+        let genlhdr = nfgenmsg(family(&tuple.src), attrs)?;
+
+        // Without NLM_F_CREATE the kernel updates the existing entry, or fails
+        // with ENOENT when there's none.
+        wait_ack(self.socket.send(
+            CtNetlinkMessage::CtNew,
+            NlmF::ACK,
+            NlPayload::Payload(genlhdr),
+        )?)
+    }
+
+    /// The set_mark call sets the connection mark of the existing conntrack entry
+    /// matching the given origin `tuple`, like the iptables `CONNMARK` target does.
+    /// Fails with `ENOENT` when there's no such entry.
+    pub fn set_mark(&self, tuple: &Tuple, mark: u32) -> Result<()> {
+        let mut attrs = GenlBuffer::<ConntrackAttr, Buffer>::new();
+        attrs.push(tuple.encode(Direction::Original.tuple_attr())?);
+        attrs.push(make_attr(
+            ConntrackAttr::CtaMark,
+            false,
+            mark.into_buffer(),
+        )?);
+
+        let genlhdr = nfgenmsg(family(&tuple.src), attrs)?;
+
+        // Like set_labels(), this updates the existing entry.
+        wait_ack(self.socket.send(
+            CtNetlinkMessage::CtNew,
+            NlmF::ACK,
+            NlPayload::Payload(genlhdr),
+        )?)
+    }
+
+    /// The delete_tuple call deletes the single conntrack entry matching the given
+    /// origin `tuple`, including both addresses and ports. Returns the number of
+    /// deleted entries, i.e. 0 when no entry matched and 1 otherwise.
+    pub fn delete_tuple(&self, tuple: &Tuple) -> Result<u32> {
+        self.delete_tuple_of(tuple, None)
+    }
+
+    /// The delete_tuple_in_zone call behaves like `delete_tuple()`, but only deletes
+    /// the entry of the given conntrack `zone`. Entries with the same tuple in other
+    /// zones are kept, which matters when zones separate overlapping address spaces.
+    pub fn delete_tuple_in_zone(&self, tuple: &Tuple, zone: u16) -> Result<u32> {
+        self.delete_tuple_of(tuple, Some(zone))
+    }
+
+    /// The delete_many call deletes the entries of all `tuples` like `delete_tuple()`,
+    /// but sends the requests in batches of `BATCH_SIZE` messages per datagram.
+    /// Only the last request of a batch asks for an acknowledgement, as the kernel
+    /// reports failing requests anyway, so a batch without failures takes one send
+    /// and one receive instead of one of each per entry. The outcome of each tuple
+    /// is returned in the order of `tuples`; a tuple without an entry fails with
+    /// `ENOENT` without affecting the others. ctnetlink doesn't support
+    /// `NFNL_MSG_BATCH_BEGIN` transactions, so the deletes of a batch are still
+    /// applied one by one. Deleting 1000 UDP entries takes about 1.2 ms this way on
+    /// Linux 6.18, against 4 ms for a loop of `delete_tuple()` calls, see the
+    /// `delete_many_beats_a_loop` test.
+    ///
+    /// Unlike the `Vec<Result<()>>` of `create_many()`, each outcome is paired with
+    /// the `nlmsg_seq` of its request, for correlating it with other traces of the
+    /// request, e.g. `nlmon` captures. Invalid tuples
+    /// are never sent, but are assigned a sequence number all the same, so the
+    /// numbers of a call are consecutive.
+    pub fn delete_many(&self, tuples: &[Tuple]) -> Result<Vec<(u32, Result<()>)>> {
+        let results = self.send_batched(tuples.iter().map(Request::delete).collect())?;
+        let deleted = results.iter().filter(|(_, result)| result.is_ok()).count();
+        log::debug!("deleted {deleted} of {} tuples in batches", tuples.len());
+
+        Ok(results)
+    }
+
+    /// The batch call returns an empty `Batch`, for sending a mix of create and
+    /// delete requests in as few datagrams as possible.
+    pub fn batch(&self) -> Batch<'_> {
+        Batch {
+            conntrack: self,
+            requests: Vec::new(),
+        }
+    }
+
+    /// Sends the requests in datagrams of `BATCH_SIZE` messages through the dump
+    /// socket, and returns the outcome of each along with its sequence number.
+    /// Requests that failed to encode are reported without being sent.
+    fn send_batched(&self, requests: Vec<Result<Request>>) -> Result<Vec<(u32, Result<()>)>> {
+        let mut socket = self
+            .dump_socket
+            .lock()
+            .map_err(|_| Error::Netlink("dump socket lock poisoned".to_string()))?;
+        let mut buffer = vec![0; DUMP_BUFFER_SIZE];
+        socket.drain(&mut buffer)?;
+
+        let mut results = Vec::with_capacity(requests.len());
+        let mut requests = requests.into_iter().peekable();
+        while requests.peek().is_some() {
+            let batch: Vec<_> = requests.by_ref().take(BATCH_SIZE).collect();
+            let first = results.len();
+            let first_seq = socket.seq.wrapping_add(1);
+            let last = batch.iter().rposition(Result::is_ok);
+
+            // Only the last request is acknowledged, which the kernel sends after
+            // the errors of all the others.
+            let mut bytes = Cursor::new(Vec::new());
+            let len = batch.len();
+            for (i, request) in batch.into_iter().enumerate() {
+                let seq = socket.next_seq();
+                let request = match request {
+                    Ok(request) => request,
+                    Err(e) => {
+                        results.push((seq, Some(Err(e))));
+                        continue;
+                    }
+                };
+                results.push((seq, None));
+
+                let flags = if Some(i) == last {
+                    request.flags | NlmF::ACK
                 } else {
-                    (ConntrackAttr::CtaTupleReply, IpTupleAttr::CtaIpv6Dst, bin)
+                    request.flags
+                };
+                NlmsghdrBuilder::default()
+                    .nl_type(request.message)
+                    .nl_flags(flags)
+                    .nl_seq(seq)
+                    .nl_payload(NlPayload::Payload(request.genlhdr))
+                    .build()?
+                    .to_bytes(&mut bytes)?;
+            }
+            let Some(last) = last else {
+                continue;
+            };
+            let bytes = bytes.into_inner();
+            retry_eintr(|| Ok(socket.socket.send(&bytes, Msg::empty())?))?;
+
+            let last_seq = first_seq.wrapping_add(last as u32);
+            let mut acknowledged = false;
+            while !acknowledged {
+                let received = socket.recv(&mut buffer)?;
+                for message in messages(&buffer[..received]) {
+                    let message = message?;
+                    let index = message.nl_seq().wrapping_sub(first_seq) as usize;
+                    if message.nl_type() != libc::NLMSG_ERROR || index >= len {
+                        continue;
+                    }
+                    results[first + index].1 = Some(match -message.nl_error() {
+                        0 => Ok(()),
+                        libc::EEXIST => Err(Error::AlreadyExists),
+                        errno => Err(Error::IO(io::Error::from_raw_os_error(errno))),
+                    });
+                    acknowledged |= message.nl_seq() == last_seq;
                 }
             }
+        }
+
+        // Requests without an error message succeeded.
+        Ok(results
+            .into_iter()
+            .map(|(seq, result)| (seq, result.unwrap_or(Ok(()))))
+            .collect())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(command = "delete", family = family(&tuple.src), zone = ?zone, deleted)
+        )
+    )]
+    fn delete_tuple_of(&self, tuple: &Tuple, zone: Option<u16>) -> Result<u32> {
+        tuple.validate()?;
+        let deleted = match self.delete_origin(&IpTuple::from(tuple), zone) {
+            Ok(()) => 1,
+            Err(e) if e.errno() == Some(libc::ENOENT) => 0,
+            Err(e) => return Err(e),
         };
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("deleted", deleted);
 
-        let ip_attr = make_attr(attr_type, false, Buffer::from(bin))?;
-        let ip_tuple = make_attr(TupleAttr::CtaTupleIp, true, ip_attr)?;
-        let proto_attr = make_attr(
-            ProtoTupleAttr::CtaProtoNum,
-            false,
-            Buffer::from((proto as u32).to_ne_bytes().to_vec()),
-        )?;
-        let proto_tuple = make_attr(TupleAttr::CtaTupleProto, true, proto_attr)?;
+        Ok(deleted)
+    }
+
+    /// The delete_between call deletes every conntrack entry whose origin tuple goes
+    /// from `src` to `dst`, regardless of protocol and ports, and returns the number
+    /// of deleted entries. The kernel only deletes entries by their complete tuple,
+    /// so the matching entries are dumped with a `DumpFilter` first and then deleted
+    /// one by one, like `delete_matching()`.
+    pub fn delete_between(&self, src: IpAddr, dst: IpAddr) -> Result<u32> {
+        let filter = DumpFilter::default().src(src).dst(dst);
+        self.delete_flows(&self.dump_filtered(&filter)?)
+    }
+
+    /// The delete_zone call deletes every conntrack entry in the given `zone`, e.g.
+    /// when the tenant owning it goes away, and returns the number of deleted
+    /// entries. Like `delete_between()`, the entries of the zone are dumped with a
+    /// `DumpFilter` first and then deleted one by one by their tuple and zone. The
+    /// zone of every dumped entry is checked again before deleting it, as older
+    /// kernels ignore `CTA_ZONE` in dump requests and would dump every zone.
+    pub fn delete_zone(&self, zone: u16) -> Result<u32> {
+        let filter = DumpFilter::default().zone(zone);
+        self.delete_flows(&self.dump_filtered(&filter)?)
+    }
+
+    /// The delete_host call deletes every conntrack entry whose origin tuple has `ip`
+    /// as its source or destination address, e.g. when the host gets banned, and
+    /// returns the number of deleted entries. The kernel filters dumps on one address
+    /// at a time, so the entries from `ip` and the entries to `ip` are dumped and
+    /// deleted in two passes, like `delete_between()`. An entry from `ip` to itself
+    /// is only counted once, as it is gone by the second pass.
+    pub fn delete_host(&self, ip: &IpAddr) -> Result<u32> {
+        let from = self.delete_flows(&self.dump_filtered(&DumpFilter::default().src(*ip))?)?;
+        let to = self.delete_flows(&self.dump_filtered(&DumpFilter::default().dst(*ip))?)?;
+
+        Ok(from + to)
+    }
+
+    /// The delete_matching call dumps the `Conntrack` table and deletes every entry
+    /// for which `pred` returns `true` by its complete tuple, returning the number
+    /// of deleted entries. A failed deletion doesn't stop the remaining ones; the
+    /// failures are collected into `Error::PartialDelete` instead. Entries that
+    /// disappear between the dump and their deletion are not counted.
+    pub fn delete_matching<F>(&self, pred: F) -> Result<u32>
+    where
+        F: FnMut(&Flow) -> bool,
+    {
+        self.delete_flows(&self.dump_where(pred)?)
+    }
+
+    fn delete_flows(&self, flows: &[Flow]) -> Result<u32> {
+        let mut deleted = 0;
+        let mut errors = Vec::new();
+        for flow in flows {
+            match self.delete_checked(flow) {
+                Ok(true) => deleted += 1,
+                Ok(false) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(deleted)
+        } else {
+            Err(Error::PartialDelete { deleted, errors })
+        }
+    }
+
+    /// The delete_checked call deletes the conntrack entry of `flow`, e.g. one
+    /// returned by `dump()`, by its origin tuple and zone. Returns `true` when the
+    /// entry was deleted and `false` when the kernel had no such entry, so cleanup
+    /// code can tell whether it did anything. Other failures are returned as errors.
+    pub fn delete_checked(&self, flow: &Flow) -> Result<bool> {
+        let origin = flow
+            .origin
+            .as_ref()
+            .ok_or(Error::IncompleteFlow("origin"))?;
+        match self.delete_origin(origin, flow.zone) {
+            Ok(()) => Ok(true),
+            Err(e) if e.errno() == Some(libc::ENOENT) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn delete_origin(&self, origin: &IpTuple, zone: Option<u16>) -> Result<()> {
+        let src = origin
+            .src
+            .ok_or(Error::InvalidTuple("missing src address"))?;
 
-        let mut attr = make_attr(top_attr_type, true, ip_tuple)?;
-        attr = attr.nest(&proto_tuple)?;
         let mut attrs = GenlBuffer::<ConntrackAttr, Buffer>::new();
-        attrs.push(attr);
+        attrs.push(origin.encode(Direction::Original.tuple_attr())?);
+        if let Some(zone) = zone {
+            attrs.push(make_attr(
+                ConntrackAttr::CtaZone,
+                false,
+                zone.into_buffer(),
+            )?);
+        }
 
-        let genlhdr = GenlmsghdrBuilder::default()
-            .cmd(libc::AF_INET as u8)
-            .version(libc::NFNETLINK_V0 as u8)
-            .attrs(attrs)
-            .build()?;
+        let genlhdr = nfgenmsg(family(&src), attrs)?;
 
-        let x: NlRouterReceiverHandle<u16, Buffer> = self.socket.send(
+        log::trace!("sending delete request: {genlhdr:?}");
+        let recv: NlRouterReceiverHandle<u16, Buffer> = self.socket.send(
             CtNetlinkMessage::CtDelete,
-            NlmF::ACK | NlmF::MATCH,
+            NlmF::ACK,
             NlPayload::Payload(genlhdr),
         )?;
 
-        log::info!("waiting...");
-        for r in x {
-            log::info!("{r:?}");
+        let result = wait_ack(recv);
+        log::debug!(
+            "delete for family {} acknowledged: {result:?}",
+            family(&src)
+        );
+        result
+    }
+
+    /// The delete call deletes every conntrack entry of protocol `proto` where `ip`
+    /// sends the traffic of `direction`, and returns the number of deleted entries.
+    /// With `Direction::Original`, `ip` is the source address of the origin tuple,
+    /// i.e. the host that initiated the connection, and with `Direction::Reply` its
+    /// destination address.
+    ///
+    /// The kernel can flush the entries matching a filter in a single request, but
+    /// doesn't report how many it deleted, so the matching entries are dumped with a
+    /// `DumpFilter` first and then deleted one by one, like `delete_matching()`.
+    /// The count is that of the deletes the kernel acknowledged. Entries created
+    /// after the dump are not deleted, and entries that disappear before their
+    /// delete are not counted. The protocol can be given as an `IpProto` or `L4Proto`,
+    /// e.g. `delete(IpProto::Tcp, &ip, Direction::Original)`, or as its raw number.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(command = "delete", family = family(ip), ?direction, deleted)
+        )
+    )]
+    pub fn delete(
+        &self,
+        proto: impl Into<IpProto>,
+        ip: &IpAddr,
+        direction: Direction,
+    ) -> Result<u32> {
+        let filter = DumpFilter::default().proto(proto);
+        let filter = match direction {
+            Direction::Original => filter.src(*ip),
+            Direction::Reply => filter.dst(*ip),
+        };
+
+        let deleted = self.delete_flows(&self.dump_filtered(&filter)?)?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("deleted", deleted);
+
+        Ok(deleted)
+    }
+}
+
+fn wait_ack(recv: NlRouterReceiverHandle<u16, Buffer>) -> Result<()> {
+    let mut interrupted = 0;
+    for result in recv {
+        match result.map_err(Error::from) {
+            Err(e) if e.errno() == Some(libc::EINTR) && interrupted < EINTR_RETRIES => {
+                interrupted += 1;
+            }
+            Err(e) if e.errno() == Some(libc::EEXIST) => return Err(Error::AlreadyExists),
+            Err(e) => return Err(e),
+            Ok(_) => {}
         }
-        log::info!("done");
+    }
 
-        Ok(())
+    Ok(())
+}
+
+/// Calls `op` again when it is interrupted by a signal, up to `EINTR_RETRIES`
+/// times.
+fn retry_eintr<T>(mut op: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut interrupted = 0;
+    loop {
+        match op() {
+            Err(e) if e.errno() == Some(libc::EINTR) && interrupted < EINTR_RETRIES => {
+                interrupted += 1;
+            }
+            result => return result,
+        }
     }
 }
 
-fn make_attr<T, P>(attr_type: T, nest: bool, payload: P) -> Result<Nlattr<T, Buffer>>
-where
-    P: Size + ToBytes,
-    T: NlAttrType,
-{
-    Ok(NlattrBuilder::default()
-        .nla_type(
-            AttrTypeBuilder::default()
-                .nla_type(attr_type)
-                .nla_nested(nest)
-                .build()?,
+/// Reports a receive that failed because of `SO_RCVTIMEO` as `Error::Timeout`.
+fn timed_out(e: Error) -> Error {
+    if e.errno() == Some(libc::EAGAIN) {
+        Error::Timeout
+    } else {
+        e
+    }
+}
+
+fn set_recv_timeout(socket: &impl AsRawFd, timeout: Option<Duration>) -> Result<()> {
+    // A zero timeval disables the timeout, so a zero duration waits 1 microsecond.
+    let timeout = timeout.map_or(Duration::ZERO, |t| t.max(Duration::from_micros(1)));
+    let timeval = libc::timeval {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+    };
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO,
+            (&raw const timeval).cast(),
+            std::mem::size_of::<libc::timeval>() as libc::socklen_t,
         )
-        .nla_payload(payload)
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    Ok(())
+}
+
+/// Receives the next datagram of an event socket with `MSG_DONTWAIT`, returning
+/// its length along with the group it was sent to, or `None` if no datagram is
+/// pending. A pending overrun is returned as `Error::Overrun`, like by `Events`.
+fn try_recv(socket: &impl AsRawFd, buffer: &mut [u8]) -> Result<Option<(usize, EventGroup)>> {
+    // SAFETY: `sockaddr_nl` is plain data, for which all zeroes is valid.
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    let mut addr_len = std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+    let received = retry_eintr(|| {
+        // SAFETY: the buffer and the address are valid for the given sizes.
+        let len = unsafe {
+            libc::recvfrom(
+                socket.as_raw_fd(),
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+                libc::MSG_DONTWAIT,
+                (&raw mut addr).cast(),
+                &mut addr_len,
+            )
+        };
+        match len {
+            -1 => Err(Error::IO(io::Error::last_os_error())),
+            len => Ok(len as usize),
+        }
+    });
+
+    match received {
+        Ok(len) => Ok(Some((len, EventGroup::from_bits_truncate(addr.nl_groups)))),
+        // EWOULDBLOCK is the same error as EAGAIN on Linux.
+        Err(e) if e.errno() == Some(libc::EAGAIN) => Ok(None),
+        Err(e) if e.errno() == Some(libc::ENOBUFS) => {
+            log::warn!("conntrack events were dropped by the kernel");
+            Err(Error::Overrun { dropped: None })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn set_netns(ns: BorrowedFd<'_>) -> Result<()> {
+    if unsafe { libc::setns(ns.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    Ok(())
+}
+
+/// Runs `op` inside the network namespace `ns` on a short-lived helper thread, so
+/// the calling thread never leaves its own namespace, or on the calling thread
+/// when `ns` is `None`.
+fn in_netns<T: Send>(
+    ns: Option<BorrowedFd<'_>>,
+    op: impl FnOnce() -> Result<T> + Send,
+) -> Result<T> {
+    let Some(ns) = ns else {
+        return op();
+    };
+    // The helper thread exits in the namespace, so it never has to be restored.
+    thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                set_netns(ns)?;
+                op()
+            })
+            .join()
+    })
+    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+fn event_socket(groups: EventGroup) -> Result<NlSocketHandle> {
+    Ok(NlSocketHandle::connect(
+        NlFamily::Netfilter,
+        Some(0),
+        Groups::new_bitmask(groups.bits()),
+    )?)
+}
+
+/// Builds the `nfgenmsg` header leading every conntrack request. It shares its
+/// layout with the generic netlink header: the address family takes the place of
+/// the command, followed by the `NFNETLINK_V0` version and a zero resource id.
+fn nfgenmsg<T: NlAttrType>(family: u8, attrs: GenlBuffer<T, Buffer>) -> Result<Genlmsghdr<u8, T>> {
+    Ok(GenlmsghdrBuilder::default()
+        .cmd(family)
+        .version(libc::NFNETLINK_V0 as u8)
+        .attrs(attrs)
         .build()?)
 }
+
+fn family(ip: &IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => libc::AF_INET as u8,
+        IpAddr::V6(_) => libc::AF_INET6 as u8,
+    }
+}
+
+/// The `DumpIter` type is a lazy iterator over the flows of a table dump, returned
+/// by `Conntrack::dump_iter()`. If the dump fails mid-stream, e.g. because the
+/// socket timed out, the failure is the last item, so a partial table is never
+/// mistaken for a complete one.
+///
+/// The kernel runs a single dump per socket at a time, so other dumps through the
+/// same `Conntrack` instance block until the iterator is dropped. Starting another
+/// dump from the thread consuming the iterator therefore deadlocks; collect the
+/// flows first instead.
+pub struct DumpIter<'a> {
+    dump: Dump<'a>,
+}
+
+impl Iterator for DumpIter<'_> {
+    type Item = Result<Flow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.dump
+            .next_message()
+            .map(|message| message.and_then(|message| message.to_owned()))
+    }
+}
+
+/// The `DumpHeaders` type is a lazy iterator over the `FlowHeader`s of a table
+/// dump, returned by `Conntrack::dump_headers()`. It behaves like `DumpIter`.
+pub struct DumpHeaders<'a> {
+    dump: Dump<'a>,
+}
+
+impl Iterator for DumpHeaders<'_> {
+    type Item = Result<FlowHeader>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.dump
+            .next_message()
+            .map(|message| message.map(|message| message.header()))
+    }
+}
+
+/// The socket requests other than dumps are sent on. neli's router receives on a
+/// thread of its own, which dropping the router only signals to exit, so the
+/// thread would stay blocked in `recv()`, holding the socket open. Closing the
+/// router also wakes the thread up with an empty message, sent from the network
+/// namespace the router was opened in, as netlink port ids are per namespace.
+struct Router {
+    router: Option<NlRouter>,
+    /// The namespace of an instance from `Conntrack::connect_in_netns()`.
+    netns: Option<OwnedFd>,
+}
+
+impl Router {
+    fn close(&mut self) -> Result<()> {
+        let Some(router) = self.router.take() else {
+            return Ok(());
+        };
+        let pid = router.pid();
+        drop(router);
+
+        let waker = in_netns(self.netns.as_ref().map(AsFd::as_fd), || {
+            Ok(NlSocket::connect(
+                NlFamily::Netfilter,
+                Some(0),
+                Groups::empty(),
+            )?)
+        })?;
+        let header = libc::nlmsghdr {
+            nlmsg_len: std::mem::size_of::<libc::nlmsghdr>() as u32,
+            nlmsg_type: libc::NLMSG_NOOP as u16,
+            nlmsg_flags: 0,
+            nlmsg_seq: 0,
+            nlmsg_pid: 0,
+        };
+        // SAFETY: `sockaddr_nl` is plain data, for which all zeroes is valid.
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_pid = pid;
+        retry_eintr(|| {
+            // SAFETY: the buffer and the address are valid for the given sizes.
+            let sent = unsafe {
+                libc::sendto(
+                    waker.as_raw_fd(),
+                    (&raw const header).cast(),
+                    std::mem::size_of::<libc::nlmsghdr>(),
+                    0,
+                    (&raw const addr).cast(),
+                    std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+                )
+            };
+            match sent {
+                -1 => Err(Error::IO(io::Error::last_os_error())),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+impl std::ops::Deref for Router {
+    type Target = NlRouter;
+
+    fn deref(&self) -> &NlRouter {
+        self.router.as_ref().expect("router used after close")
+    }
+}
+
+impl Drop for Router {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            log::warn!("failed to stop the netlink router thread: {e}");
+        }
+    }
+}
+
+/// The socket dumps are received on. Unlike the router socket, it is read
+/// directly, so dump messages can be viewed in place and receives can time out.
+struct DumpSocket {
+    socket: NlSocket,
+    seq: u32,
+    /// The sequence number of a dump that was abandoned before its `NLMSG_DONE`.
+    /// The kernel rejects new dumps on the socket until its rest is read.
+    unfinished: Option<u32>,
+}
+
+/// A dump in progress on the `DumpSocket`, sending the request for each address
+/// family once the previous one is done.
+struct Dump<'a> {
+    socket: MutexGuard<'a, DumpSocket>,
+    nl_type: CtNetlinkMessage,
+    requests: VecDeque<Genlmsghdr<u8, ConntrackAttr>>,
+    active: Option<u32>,
+    buffer: Vec<u8>,
+    received: Range<usize>,
+    filter: DumpFilter,
+    replies: usize,
+    failed: bool,
+}
+
+impl<'a> Dump<'a> {
+    fn new(
+        conntrack: &'a Conntrack,
+        filter: &DumpFilter,
+        nl_type: CtNetlinkMessage,
+    ) -> Result<Dump<'a>> {
+        let mut requests = VecDeque::new();
+        for family in filter.families() {
+            requests.push_back(nfgenmsg(family, filter.encode()?)?);
+        }
+
+        let socket = conntrack
+            .dump_socket
+            .lock()
+            .map_err(|_| Error::Netlink("dump socket lock poisoned".to_string()))?;
+        let mut dump = Dump {
+            socket,
+            nl_type,
+            requests,
+            active: None,
+            buffer: vec![0; DUMP_BUFFER_SIZE],
+            received: 0..0,
+            filter: filter.clone(),
+            replies: 0,
+            failed: false,
+        };
+        // The first request is sent right away, so it fails early, e.g. when the
+        // kernel doesn't support the filter.
+        if let Some(genlhdr) = dump.requests.pop_front() {
+            dump.active = Some(dump.send(genlhdr)?);
+        }
+
+        Ok(dump)
+    }
+
+    /// Returns the next flow message of the dump, or `None` once the dumps of all
+    /// families are done. Nothing is returned after an error.
+    fn next_message(&mut self) -> Option<Result<FlowRef<'_>>> {
+        if self.failed {
+            return None;
+        }
+
+        match self.advance() {
+            Ok(Some(range)) => Some(FlowRef::from_bytes(&self.buffer[range])),
+            Ok(None) => None,
+            Err(e) => {
+                self.failed = true;
+                self.requests.clear();
+                Some(Err(e))
+            }
+        }
+    }
+
+    fn advance(&mut self) -> Result<Option<Range<usize>>> {
+        loop {
+            let Some(seq) = self.active else {
+                let Some(genlhdr) = self.requests.pop_front() else {
+                    return Ok(None);
+                };
+                self.active = Some(self.send(genlhdr)?);
+                continue;
+            };
+            if self.received.is_empty() {
+                self.received = 0..self.recv()?;
+            }
+
+            let start = self.received.start;
+            let message = FlowRef::from_bytes(&self.buffer[self.received.clone()])?;
+            let len = message.as_bytes().len();
+            self.received.start = (start + len.next_multiple_of(4)).min(self.received.end);
+
+            // Messages of a drained dump may still follow the one that failed.
+            if message.nl_seq() != seq {
+                continue;
+            }
+            // The kernel flags every message sent after the table changed, the
+            // rest of the dump is drained by the next request.
+            if message.nl_flags() & libc::NLM_F_DUMP_INTR != 0 {
+                self.active = None;
+                return Err(Error::DumpInterrupted);
+            }
+            match message.nl_type() {
+                libc::NLMSG_DONE => {
+                    log::debug!("dump {seq} done with {} flows", self.replies);
+                    self.finish();
+                }
+                libc::NLMSG_ERROR => {
+                    let errno = message.nl_error();
+                    self.finish();
+                    if errno != 0 {
+                        return Err(Error::IO(io::Error::from_raw_os_error(-errno)));
+                    }
+                }
+                _ if !self.filter.matches_zone(message.zone()) => {}
+                _ if !self.filter.matches_src_port(message.src_port()) => {}
+                _ if !self.filter.matches_dst_port(message.dst_port()) => {}
+                _ if !self.filter.matches_helper(message.helper_name()) => {}
+                _ => {
+                    self.replies += 1;
+                    return Ok(Some(start..start + len));
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.active = None;
+        self.replies = 0;
+        self.socket.unfinished = None;
+    }
+
+    /// Sends a dump request after reading the rest of an abandoned dump, and
+    /// returns its sequence number.
+    fn send(&mut self, genlhdr: Genlmsghdr<u8, ConntrackAttr>) -> Result<u32> {
+        self.socket.drain(&mut self.buffer)?;
+
+        let seq = self.socket.next_seq();
+        log::debug!("sending dump request {seq} for family {}", genlhdr.cmd());
+        let request = NlmsghdrBuilder::default()
+            .nl_type(self.nl_type)
+            .nl_flags(NlmF::REQUEST | NlmF::DUMP)
+            .nl_seq(seq)
+            .nl_payload(NlPayload::Payload(genlhdr))
+            .build()?;
+        let mut bytes = Cursor::new(Vec::new());
+        request.to_bytes(&mut bytes)?;
+        let bytes = bytes.into_inner();
+        retry_eintr(|| Ok(self.socket.socket.send(&bytes, Msg::empty())?))?;
+        self.socket.unfinished = Some(seq);
+
+        Ok(seq)
+    }
+
+    fn recv(&mut self) -> Result<usize> {
+        self.socket.recv(&mut self.buffer)
+    }
+}
+
+impl DumpSocket {
+    fn next_seq(&mut self) -> u32 {
+        self.seq = self.seq.wrapping_add(1);
+        self.seq
+    }
+
+    /// Reads the rest of an abandoned dump, if any, so the socket accepts new
+    /// requests.
+    fn drain(&mut self, buffer: &mut [u8]) -> Result<()> {
+        let Some(seq) = self.unfinished else {
+            return Ok(());
+        };
+        log::debug!("draining abandoned dump {seq}");
+        loop {
+            let len = self.recv(buffer)?;
+            for message in messages(&buffer[..len]) {
+                let message = message?;
+                if message.nl_seq() == seq
+                    && matches!(message.nl_type(), libc::NLMSG_DONE | libc::NLMSG_ERROR)
+                {
+                    self.unfinished = None;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn recv(&self, buffer: &mut [u8]) -> Result<usize> {
+        let (len, _) =
+            retry_eintr(|| Ok(self.socket.recv(&mut *buffer, Msg::empty())?)).map_err(timed_out)?;
+        if len == 0 {
+            return Err(Error::Truncated);
+        }
+
+        Ok(len)
+    }
+}
+
+/// Iterates over the netlink messages of a received datagram, stopping after the
+/// first malformed one.
+fn messages(mut received: &[u8]) -> impl Iterator<Item = Result<FlowRef<'_>>> {
+    std::iter::from_fn(move || {
+        if received.is_empty() {
+            return None;
+        }
+        let message = FlowRef::from_bytes(received);
+        let len = message
+            .as_ref()
+            .map_or(received.len(), |m| m.as_bytes().len());
+        received = received.get(len.next_multiple_of(4)..).unwrap_or_default();
+        Some(message)
+    })
+}
+
+/// The `EventStream` type is a blocking iterator over the conntrack events of all
+/// groups, returned by `Conntrack::watch_and_dump()`. It owns its event socket,
+/// and otherwise behaves like `Events`.
+pub struct EventStream {
+    socket: NlSocketHandle,
+    pending: VecDeque<Result<Event>>,
+}
+
+impl Iterator for EventStream {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        next_event(&self.socket, EventGroup::all(), &mut self.pending)
+    }
+}
+
+/// The `Events` type is a blocking iterator over conntrack multicast events,
+/// returned by `Conntrack::events()`. When the kernel drops events because the
+/// socket buffer is full, the iterator yields `Error::Overrun` and keeps receiving
+/// the events that follow. Per-connection state built from the events may be stale
+/// after an overrun, and can be resynchronized with a dump.
+pub struct Events<'a> {
+    socket: &'a NlSocketHandle,
+    types: EventGroup,
+    tuple: Option<IpTuple>,
+    pending: VecDeque<Result<Event>>,
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let event = next_event(self.socket, self.types, &mut self.pending)?;
+            let Some(tuple) = &self.tuple else {
+                return Some(event);
+            };
+            let matches = |t: &Option<IpTuple>| t.as_ref().is_some_and(|t| tuple.matches(t));
+            match &event {
+                Ok(e) if !matches(&e.flow.origin) && !matches(&e.flow.reply) => {}
+                _ => return Some(event),
+            }
+        }
+    }
+}
+
+/// The `Batch` type collects create and delete requests, returned by
+/// `Conntrack::batch()`. `commit()` sends them like `Conntrack::delete_many()`, in
+/// datagrams of `BATCH_SIZE` messages with only the last one acknowledged, and
+/// returns the outcome of each request in the order they were added, paired with
+/// its sequence number. A failing request, e.g. a create of an existing entry
+/// failing with `Error::AlreadyExists`, doesn't affect the others.
+///
+/// The batch is not atomic. ctnetlink doesn't implement the commit and abort
+/// callbacks of `NFNL_MSG_BATCH_BEGIN` transactions, and rejects them with
+/// `EOPNOTSUPP`, so the kernel applies the requests one by one, and the requests
+/// before a failing one stay applied. Packets can also create or update entries
+/// between two requests.
+///
+/// ```no_run
+/// use conntrack::{Conntrack, model::*};
+/// use std::net::{IpAddr, Ipv4Addr};
+/// use std::time::Duration;
+///
+/// let ct = Conntrack::connect()?;
+/// let flow = FlowBuilder::default()
+///     .proto(L4Proto::Udp)
+///     .src(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))
+///     .dst(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)))
+///     .src_port(5353)
+///     .dst_port(53)
+///     .timeout(Duration::from_secs(120))
+///     .build()?;
+/// let stale = TupleBuilder::default()
+///     .proto(L4Proto::Udp)
+///     .src(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))
+///     .dst(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3)))
+///     .src_port(5353)
+///     .dst_port(53)
+///     .build()?;
+///
+/// for (seq, result) in ct.batch().create(&flow).delete(&stale).commit()? {
+///     if let Err(e) = result {
+///         println!("request {seq} failed: {e}");
+///     }
+/// }
+/// # Ok::<(), conntrack::Error>(())
+/// ```
+pub struct Batch<'a> {
+    conntrack: &'a Conntrack,
+    requests: Vec<Result<Request>>,
+}
+
+impl Batch<'_> {
+    /// Adds a request injecting `flow`, like `Conntrack::create()`.
+    pub fn create(mut self, flow: &NewFlow) -> Self {
+        self.requests.push(Request::create(flow));
+        self
+    }
+
+    /// Adds a request deleting the entry of the origin `tuple`, like
+    /// `Conntrack::delete_tuple()`.
+    pub fn delete(mut self, tuple: &Tuple) -> Self {
+        self.requests.push(Request::delete(tuple));
+        self
+    }
+
+    /// Returns the number of requests in the batch.
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Returns `true` if no requests were added.
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Sends the requests and returns the outcome of each, in the order they were
+    /// added. Requests that failed to encode, e.g. of an invalid tuple, fail without
+    /// being sent. Fails as a whole only when the socket fails.
+    pub fn commit(self) -> Result<Vec<(u32, Result<()>)>> {
+        self.conntrack.send_batched(self.requests)
+    }
+}
+
+/// A request of a `Batch`, encoded when it is added.
+struct Request {
+    message: CtNetlinkMessage,
+    flags: NlmF,
+    genlhdr: Genlmsghdr<u8, ConntrackAttr>,
+}
+
+impl Request {
+    fn create(flow: &NewFlow) -> Result<Self> {
+        Ok(Request {
+            message: CtNetlinkMessage::CtNew,
+            flags: NlmF::REQUEST | NlmF::CREATE | NlmF::EXCL,
+            genlhdr: nfgenmsg(family(&flow.origin.src), flow.encode()?)?,
+        })
+    }
+
+    fn delete(tuple: &Tuple) -> Result<Self> {
+        let mut attrs = GenlBuffer::<ConntrackAttr, Buffer>::new();
+        attrs.push(tuple.encode(Direction::Original.tuple_attr())?);
+
+        Ok(Request {
+            message: CtNetlinkMessage::CtDelete,
+            flags: NlmF::REQUEST,
+            genlhdr: nfgenmsg(family(&tuple.src), attrs)?,
+        })
+    }
+}
+
+/// The `Watcher` type keeps a `FlowTable` in sync with the conntrack table,
+/// returned by `Conntrack::watch()`. It is a blocking iterator over the events
+/// applied to the table: `EventType::New` and `EventType::Update` events insert
+/// their flow, and `EventType::Destroy` events remove it. When the kernel drops
+/// events, the table is rebuilt from a new dump instead of yielding
+/// `Error::Overrun`, so it never stays stale.
+pub struct Watcher<'a> {
+    conntrack: &'a Conntrack,
+    socket: NlSocketHandle,
+    pending: VecDeque<Result<Event>>,
+    table: FlowTable,
+}
+
+impl Watcher<'_> {
+    /// Returns the table as of the last event returned by the iterator.
+    pub fn table(&self) -> &FlowTable {
+        &self.table
+    }
+
+    /// Returns a copy of the table as of the last event returned by the iterator.
+    pub fn snapshot(&self) -> FlowTable {
+        self.table.clone()
+    }
+
+    fn apply(&mut self, event: &Event) {
+        match event.event_type {
+            EventType::New | EventType::Update => {
+                self.table.insert(event.flow.clone());
+            }
+            EventType::Destroy => {
+                if let Some(id) = event.flow.id {
+                    self.table.remove(id);
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Watcher<'_> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match next_event(&self.socket, EventGroup::all(), &mut self.pending)? {
+                Ok(event) => {
+                    self.apply(&event);
+                    return Some(Ok(event));
+                }
+                Err(Error::Overrun { .. }) => {
+                    log::warn!("resynchronizing the flow table after dropped events");
+                    match self.conntrack.dump() {
+                        Ok(flows) => self.table = flows.into_iter().collect(),
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Returns the next event of the given `types` received on `socket`, buffering
+/// the other events of the same datagram in `pending`.
+fn next_event(
+    socket: &NlSocketHandle,
+    types: EventGroup,
+    pending: &mut VecDeque<Result<Event>>,
+) -> Option<Result<Event>> {
+    while pending.is_empty() {
+        let received =
+            retry_eintr(|| Ok(socket.recv::<CtNetlinkMessage, Genlmsghdr<u8, ConntrackAttr>>()?));
+        let (iter, groups) = match received {
+            Ok(received) => received,
+            Err(e) => {
+                let e = timed_out(e);
+                if e.errno() == Some(libc::ENOBUFS) {
+                    log::warn!("conntrack events were dropped by the kernel");
+                    return Some(Err(Error::Overrun { dropped: None }));
+                }
+                return Some(Err(e));
+            }
+        };
+        let group = EventGroup::from_bits_truncate(groups.as_bitmask().unwrap_or_default());
+        if !types.intersects(group) {
+            continue;
+        }
+
+        for result in iter {
+            let event = result
+                .map_err(Error::from)
+                .and_then(|message| decode_event(message, group));
+            pending.push_back(event);
+        }
+    }
+
+    pending.pop_front()
+}
+
+fn decode_event(
+    message: Nlmsghdr<CtNetlinkMessage, Genlmsghdr<u8, ConntrackAttr>>,
+    group: EventGroup,
+) -> Result<Event> {
+    let created = message.nl_flags().contains(NlmF::CREATE);
+    let event_type = match (*message.nl_type(), created) {
+        (CtNetlinkMessage::CtNew, true) => EventType::New,
+        (CtNetlinkMessage::CtNew, false) => EventType::Update,
+        (CtNetlinkMessage::CtDelete, _) => EventType::Destroy,
+        (other, _) => {
+            return Err(Error::Netlink(format!(
+                "unexpected event message type: {other:?}"
+            )));
+        }
+    };
+
+    match message.nl_payload() {
+        NlPayload::Payload(payload) => Ok(Event {
+            group,
+            event_type,
+            flow: Flow::decode_message(payload)?,
+        }),
+        other => Err(Error::Netlink(format!(
+            "unexpected event payload: {other:?}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    // The tests below talk to the kernel and need CAP_NET_ADMIN, run them with
+    // `cargo test -- --ignored` as root. They only touch entries of the
+    // 192.0.2.0/24 documentation range.
+
+    fn udp_tuple(src_port: u16) -> Tuple {
+        TupleBuilder::default()
+            .proto(L4Proto::Udp)
+            .src("192.0.2.1".parse().unwrap())
+            .dst("192.0.2.2".parse().unwrap())
+            .src_port(src_port)
+            .dst_port(53)
+            .build()
+            .unwrap()
+    }
+
+    fn udp_flow(src_port: u16, zone: u16) -> NewFlow {
+        FlowBuilder::default()
+            .proto(L4Proto::Udp)
+            .src("192.0.2.1".parse().unwrap())
+            .dst("192.0.2.2".parse().unwrap())
+            .src_port(src_port)
+            .dst_port(53)
+            .timeout(Duration::from_secs(60))
+            .zone(zone)
+            .build()
+            .unwrap()
+    }
+
+    /// Every test uses source ports of its own, as the tests run concurrently.
+    fn test_flows(ct: &Conntrack, src_port: u16) -> Vec<Flow> {
+        let filter = DumpFilter::default()
+            .src("192.0.2.1".parse().unwrap())
+            .src_ports(&[src_port]);
+        ct.dump_filtered(&filter).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs CAP_NET_ADMIN"]
+    fn get_missing_entry_is_none() {
+        let ct = Conntrack::connect().unwrap();
+
+        assert!(ct.get(&udp_tuple(1)).unwrap().is_none());
+    }
+
+    #[test]
+    #[ignore = "needs CAP_NET_ADMIN"]
+    fn create_icmp_echo_flow() {
+        let ct = Conntrack::connect().unwrap();
+        let flow = FlowBuilder::default()
+            .proto(L4Proto::Icmp)
+            .src("192.0.2.1".parse().unwrap())
+            .dst("192.0.2.2".parse().unwrap())
+            .icmp_id(12)
+            .icmp_type(8)
+            .icmp_code(0)
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap();
+
+        let created = ct.create(&flow);
+        let entry = ct.get(&flow.origin).unwrap();
+        ct.delete_tuple(&flow.origin).unwrap();
+
+        created.unwrap();
+        let reply = entry.unwrap().reply.unwrap().proto.unwrap();
+        assert_eq!(
+            (reply.icmp_id, reply.icmp_type, reply.icmp_code),
+            (Some(12), Some(0), Some(0))
+        );
+    }
+
+    #[test]
+    #[ignore = "needs CAP_NET_ADMIN"]
+    fn delete_tuple_in_zone_keeps_other_zones() {
+        let ct = Conntrack::connect().unwrap();
+        let tuple = udp_tuple(2);
+        ct.create(&udp_flow(2, 1)).unwrap();
+        ct.create(&udp_flow(2, 2)).unwrap();
+
+        let deleted = ct.delete_tuple_in_zone(&tuple, 1);
+        let zones: Vec<_> = test_flows(&ct, 2).iter().map(|flow| flow.zone).collect();
+        ct.delete_tuple_in_zone(&tuple, 2).unwrap();
+
+        assert_eq!(deleted.unwrap(), 1);
+        assert_eq!(zones, [Some(2)]);
+    }
+
+    /// Opens a new, empty network namespace, entered by a helper thread only.
+    fn new_netns() -> File {
+        thread::spawn(|| {
+            assert_eq!(unsafe { libc::unshare(libc::CLONE_NEWNET) }, 0);
+            File::open("/proc/thread-self/ns/net").unwrap()
+        })
+        .join()
+        .unwrap()
+    }
+
+    /// Counts the netfilter netlink sockets userspace has open in the namespace
+    /// `ns`, leaving out the kernel socket with port id 0.
+    fn netfilter_sockets(ns: &File) -> usize {
+        let sockets = in_netns(Some(ns.as_fd()), || {
+            Ok(std::fs::read_to_string("/proc/thread-self/net/netlink")?)
+        })
+        .unwrap();
+        sockets
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().skip(1).take(2).collect::<Vec<_>>())
+            .filter(|fields| fields[0] == "12" && fields[1] != "0")
+            .count()
+    }
+
+    #[test]
+    #[ignore = "needs CAP_SYS_ADMIN"]
+    fn close_wakes_the_router_of_a_netns_instance() {
+        let ns = new_netns();
+        let ct = Conntrack::connect_in_netns_fd(&ns).unwrap();
+        ct.dump().unwrap();
+        assert_ne!(netfilter_sockets(&ns), 0);
+
+        ct.close().unwrap();
+        // The router thread closes its socket once it wakes up.
+        let mut open = netfilter_sockets(&ns);
+        for _ in 0..100 {
+            if open == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+            open = netfilter_sockets(&ns);
+        }
+        assert_eq!(open, 0);
+    }
+
+    #[test]
+    #[ignore = "needs CAP_SYS_ADMIN"]
+    fn subscribe_in_netns_receives_its_events() {
+        let mut ct = Conntrack::connect_in_netns_fd(new_netns()).unwrap();
+        ct.subscribe(EventGroup::NEW).unwrap();
+        ct.set_timeout(Some(Duration::from_secs(1))).unwrap();
+        ct.create(&udp_flow(4, 0)).unwrap();
+
+        let event = ct.events().unwrap().next().unwrap().unwrap();
+        let origin = event.flow.origin.unwrap();
+        assert_eq!(event.event_type, EventType::New);
+        assert_eq!(origin.proto.and_then(|p| p.src_port), Some(4));
+    }
+
+    #[test]
+    #[ignore = "needs CAP_SYS_ADMIN"]
+    fn try_recv_event_does_not_block() {
+        let mut ct = Conntrack::connect_in_netns_fd(new_netns()).unwrap();
+        ct.subscribe(EventGroup::all()).unwrap();
+        assert!(ct.try_recv_event().unwrap().is_none());
+
+        ct.create(&udp_flow(5, 0)).unwrap();
+        let event = ct.try_recv_event().unwrap().unwrap();
+        assert_eq!(event.event_type, EventType::New);
+        assert!(ct.try_recv_event().unwrap().is_none());
+    }
+
+    #[test]
+    #[ignore = "needs CAP_NET_ADMIN"]
+    fn create_many_reports_invalid_entries() {
+        let ct = Conntrack::connect().unwrap();
+        let mut invalid = udp_flow(7, 0);
+        invalid.origin.dst = "2001:db8::2".parse().unwrap();
+
+        let results = ct
+            .create_many(&[udp_flow(6, 0), invalid, udp_flow(7, 0)])
+            .unwrap();
+        for src_port in [6, 7] {
+            ct.delete_tuple(&udp_tuple(src_port)).unwrap();
+        }
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok() && results[2].is_ok());
+        assert!(matches!(results[1], Err(Error::InvalidTuple(_))));
+    }
+
+    #[test]
+    #[ignore = "needs CAP_NET_ADMIN"]
+    fn dump_filtered_by_mark() {
+        let ct = Conntrack::connect().unwrap();
+        for src_port in [8, 9] {
+            ct.create(&udp_flow(src_port, 0)).unwrap();
+        }
+        ct.set_mark(&udp_tuple(8), 0x1235).unwrap();
+        ct.set_mark(&udp_tuple(9), 0x1240).unwrap();
+
+        let filter = DumpFilter::default()
+            .src("192.0.2.1".parse().unwrap())
+            .mark(0x1230, 0xfff0);
+        let flows = ct.dump_filtered(&filter);
+        for src_port in [8, 9] {
+            ct.delete_tuple(&udp_tuple(src_port)).unwrap();
+        }
+
+        let marked: Vec<_> = flows
+            .unwrap()
+            .into_iter()
+            .filter_map(|flow| {
+                let src_port = flow.origin?.proto?.src_port?;
+                [8, 9].contains(&src_port).then_some((src_port, flow.mark))
+            })
+            .collect();
+        assert_eq!(marked, [(8, Some(0x1235))]);
+    }
+
+    #[test]
+    #[ignore = "needs CAP_NET_ADMIN"]
+    fn dump_filtered_by_status() {
+        let ct = Conntrack::connect().unwrap();
+        let assured = FlowBuilder::default()
+            .proto(L4Proto::Udp)
+            .src("192.0.2.1".parse().unwrap())
+            .dst("192.0.2.2".parse().unwrap())
+            .src_port(10)
+            .dst_port(53)
+            .timeout(Duration::from_secs(60))
+            .status(StatusFlags::StatusSeenReply | StatusFlags::StatusAssured)
+            .build()
+            .unwrap();
+        ct.create(&assured).unwrap();
+        ct.create(&udp_flow(11, 0)).unwrap();
+
+        let filter = DumpFilter::default()
+            .src("192.0.2.1".parse().unwrap())
+            .status(StatusFlags::StatusAssured, StatusFlags::StatusAssured);
+        let flows = ct.dump_filtered(&filter);
+        for src_port in [10, 11] {
+            ct.delete_tuple(&udp_tuple(src_port)).unwrap();
+        }
+
+        let ports: Vec<_> = flows
+            .unwrap()
+            .into_iter()
+            .filter_map(|flow| flow.origin?.proto?.src_port)
+            .filter(|src_port| [10, 11].contains(src_port))
+            .collect();
+        assert_eq!(ports, [10]);
+    }
+
+    #[test]
+    #[ignore = "needs CAP_SYS_ADMIN"]
+    fn delete_many_beats_a_loop() {
+        use std::time::Instant;
+
+        let ct = Conntrack::connect_in_netns_fd(new_netns()).unwrap();
+        let flows: Vec<_> = (0..1000).map(|src_port| udp_flow(src_port, 0)).collect();
+        let tuples: Vec<_> = (0..1000).map(udp_tuple).collect();
+
+        ct.create_many(&flows).unwrap();
+        let start = Instant::now();
+        for tuple in &tuples {
+            assert_eq!(ct.delete_tuple(tuple).unwrap(), 1);
+        }
+        let looped = start.elapsed();
+
+        ct.create_many(&flows).unwrap();
+        let start = Instant::now();
+        let results = ct.delete_many(&tuples).unwrap();
+        let batched = start.elapsed();
+
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        assert!(batched < looped, "{batched:?} batched, {looped:?} looped");
+    }
+
+    fn _assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn conntrack_is_send_and_sync() {
+        _assert_send_sync::<Conntrack>();
+    }
+
+    #[test]
+    #[ignore = "needs CAP_NET_ADMIN"]
+    fn concurrent_dumps_wait_for_each_other() {
+        let ct = Arc::new(Conntrack::connect().unwrap());
+        let dumps: Vec<_> = (0..4)
+            .map(|_| {
+                let ct = Arc::clone(&ct);
+                thread::spawn(move || (0..20).try_for_each(|_| ct.dump().map(drop)))
+            })
+            .collect();
+
+        for dump in dumps {
+            let result = dump.join().unwrap();
+            assert!(
+                result.as_ref().err().and_then(Error::errno) != Some(libc::EBUSY),
+                "{result:?}"
+            );
+            result.unwrap();
+        }
+    }
+
+    #[test]
+    #[ignore = "needs CAP_NET_ADMIN"]
+    fn delete_zone_keeps_other_zones() {
+        let ct = Conntrack::connect().unwrap();
+        // delete_zone() deletes the entries of every test in the zone, so this
+        // test uses zones of its own.
+        for zone in [0, 7, 8] {
+            ct.create(&udp_flow(3, zone)).unwrap();
+        }
+
+        let deleted = ct.delete_zone(7);
+        let zones: Vec<_> = test_flows(&ct, 3).iter().map(|flow| flow.zone).collect();
+        for zone in [0, 8] {
+            ct.delete_tuple_in_zone(&udp_tuple(3), zone).unwrap();
+        }
+
+        assert_eq!(deleted.unwrap(), 1);
+        assert_eq!(zones.len(), 2);
+        assert!(zones.contains(&None) && zones.contains(&Some(8)));
+    }
+}
@@ -0,0 +1,133 @@
+//! # CSV
+//! This module contains the CSV export of flows. It requires the `csv` feature.
+
+use std::fmt::Display;
+use std::io::{self, Write};
+
+use crate::model::*;
+
+const CSV_HEADER: [&str; 14] = [
+    "id",
+    "protocol",
+    "src",
+    "dst",
+    "sport",
+    "dport",
+    "state",
+    "mark",
+    "orig_packets",
+    "orig_bytes",
+    "reply_packets",
+    "reply_bytes",
+    "helper",
+    "secctx",
+];
+
+impl Flow {
+    /// Writes the CSV header line matching the rows of `write_csv_row()`.
+    pub fn write_csv_header(w: &mut impl Write) -> io::Result<()> {
+        write!(w, "{}\r\n", CSV_HEADER.join(","))
+    }
+
+    /// Writes the flow as one CSV line, following RFC 4180, which ends lines with
+    /// CRLF. The addresses and ports
+    /// are those of the origin tuple, and the state is the TCP state. Fields the
+    /// flow doesn't carry, e.g. the ports of ICMP flows or the counters when
+    /// accounting is disabled, are left empty.
+    pub fn write_csv_row(&self, w: &mut impl Write) -> io::Result<()> {
+        let origin = self.origin.as_ref();
+        let proto = origin.and_then(|o| o.proto.as_ref());
+        let counter = |counter: &Option<Counter>| {
+            let counter = counter.as_ref();
+            (
+                field(counter.and_then(|c| c.packets)),
+                field(counter.and_then(|c| c.bytes)),
+            )
+        };
+        let (orig_packets, orig_bytes) = counter(&self.counter_origin);
+        let (reply_packets, reply_bytes) = counter(&self.counter_reply);
+        let state = self
+            .proto_info
+            .as_ref()
+            .and_then(|info| info.tcp.as_ref())
+            .and_then(|tcp| tcp.state);
+
+        let fields = [
+            field(self.id),
+            field(self.proto()),
+            field(origin.and_then(|o| o.src)),
+            field(origin.and_then(|o| o.dst)),
+            field(proto.and_then(|p| p.src_port)),
+            field(proto.and_then(|p| p.dst_port)),
+            field(state),
+            field(self.mark),
+            orig_packets,
+            orig_bytes,
+            reply_packets,
+            reply_bytes,
+            escape(self.helper.as_ref().and_then(|h| h.name.as_deref())),
+            escape(self.sec_ctx.as_ref().and_then(|s| s.name.as_deref())),
+        ];
+        write!(w, "{}\r\n", fields.join(","))
+    }
+}
+
+fn field(value: Option<impl Display>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Quotes a string field if it contains a separator, a quote or a line break, and
+/// doubles the quotes inside it.
+fn escape(value: Option<&str>) -> String {
+    let value = value.unwrap_or_default();
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(flow: &Flow) -> String {
+        let mut out = Vec::new();
+        flow.write_csv_row(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn escape_quotes_special_characters() {
+        assert_eq!(escape(Some("ftp")), "ftp");
+        assert_eq!(escape(Some("a,b")), "\"a,b\"");
+        assert_eq!(escape(Some("say \"hi\"")), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape(Some("two\nlines")), "\"two\nlines\"");
+        assert_eq!(escape(None), "");
+    }
+
+    #[test]
+    fn row_quotes_the_helper_name() {
+        let flow = Flow {
+            helper: Some(Helper {
+                name: Some("a,\"b\"\nc".to_owned()),
+                info: None,
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(row(&flow), ",,,,,,,,,,,,\"a,\"\"b\"\"\nc\",\r\n");
+    }
+
+    #[test]
+    fn row_without_counters_has_empty_fields() {
+        let flow = Flow {
+            id: Some(7),
+            mark: Some(1),
+            ..Default::default()
+        };
+
+        assert_eq!(row(&flow), "7,,,,,,,1,,,,,,\r\n");
+        assert_eq!(row(&flow).matches(',').count(), CSV_HEADER.len() - 1);
+    }
+}
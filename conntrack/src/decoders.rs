@@ -7,12 +7,18 @@ use std::net::Ipv4Addr;
 use std::net::Ipv6Addr;
 use std::time::Duration;
 
+use std::io::Cursor;
+
 use chrono::TimeZone;
 use chrono::Utc;
+use neli::FromBytes;
+use neli::genl::Genlmsghdr;
+use neli::nl::{NlPayload, Nlmsghdr};
 use neli::types::Buffer;
 use neli::{attr::Attribute, consts::genl::*, genl::Nlattr};
 
 use crate::attributes::*;
+use crate::message::*;
 use crate::model::*;
 use crate::result::*;
 
@@ -35,7 +41,41 @@ where
     fn decode(attr: &Nlattr<T, Buffer>) -> Result<M>;
 }
 
-/// A decoder capable of decoding `IpAddr` instances from an Attribute.
+/// A decoder capable of decoding `IpAddr` instances from an Attribute. The payload
+/// must be exactly 4 bytes for IPv4 and 16 bytes for IPv6 addresses, otherwise
+/// decoding fails with `Error::BadAttrLen` instead of truncating the address.
+///
+/// ```rust
+/// use conntrack::model::*;
+/// use conntrack::Error;
+///
+/// // A UDP flow from [2001:db8::1]:5353 to [2001:db8::2]:53.
+/// let raw = [
+///     0x60, 0x00, 0x00, 0x00, 0x00, 0x01, 0x22, 0x00, 0x00, 0x00, 0x00, 0x00,
+///     0x00, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x4c, 0x00, 0x01, 0x80,
+///     0x2c, 0x00, 0x01, 0x80, 0x14, 0x00, 0x03, 0x00, 0x20, 0x01, 0x0d, 0xb8,
+///     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+///     0x14, 0x00, 0x04, 0x00, 0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+///     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x1c, 0x00, 0x02, 0x80,
+///     0x05, 0x00, 0x01, 0x00, 0x11, 0x00, 0x00, 0x00, 0x06, 0x00, 0x02, 0x00,
+///     0x14, 0xe9, 0x00, 0x00, 0x06, 0x00, 0x03, 0x00, 0x00, 0x35, 0x00, 0x00,
+/// ];
+/// let origin = Flow::from_bytes(&raw).unwrap().origin.unwrap();
+/// assert_eq!(origin.src, Some("2001:db8::1".parse().unwrap()));
+/// assert_eq!(origin.dst, Some("2001:db8::2".parse().unwrap()));
+///
+/// // An IPv6 address sent as CTA_IP_V4_SRC.
+/// let raw = [
+///     0x30, 0x00, 0x00, 0x00, 0x00, 0x01, 0x22, 0x00, 0x00, 0x00, 0x00, 0x00,
+///     0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x1c, 0x00, 0x01, 0x80,
+///     0x18, 0x00, 0x01, 0x80, 0x14, 0x00, 0x01, 0x00, 0x20, 0x01, 0x0d, 0xb8,
+///     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+/// ];
+/// assert!(matches!(
+///     Flow::from_bytes(&raw),
+///     Err(Error::BadAttrLen { expected: 4, len: 16 })
+/// ));
+/// ```
 pub trait IpDecoder<T>
 where
     T: NlAttrType,
@@ -46,15 +86,24 @@ where
 
 impl<T: NlAttrType> IpDecoder<T> for IpAddr {
     fn decode_v4(attr: &Nlattr<T, Buffer>) -> Result<IpAddr> {
-        let ip: Ipv4Addr = Ipv4Addr::from(u32::decode(attr)?);
-        Ok(IpAddr::V4(ip))
+        Ok(IpAddr::V4(Ipv4Addr::from(octets::<T, 4>(attr)?)))
     }
     fn decode_v6(attr: &Nlattr<T, Buffer>) -> Result<IpAddr> {
-        let ip: Ipv6Addr = Ipv6Addr::from(u128::decode(attr)?);
-        Ok(IpAddr::V6(ip))
+        Ok(IpAddr::V6(Ipv6Addr::from(octets::<T, 16>(attr)?)))
     }
 }
 
+/// Returns the payload of an address attribute, which must be exactly `N` bytes.
+/// Reading it as an integer would silently take the first 4 bytes of an IPv6
+/// address as an IPv4 address.
+fn octets<T: NlAttrType, const N: usize>(attr: &Nlattr<T, Buffer>) -> Result<[u8; N]> {
+    let payload = attr.nla_payload().as_ref();
+    payload.try_into().map_err(|_| Error::BadAttrLen {
+        expected: N,
+        len: payload.len(),
+    })
+}
+
 impl<T: NlAttrType> PrimitiveAttrDecoder<T, u128> for u128 {
     fn decode(attr: &Nlattr<T, Buffer>) -> Result<u128> {
         Ok(u128::from_be(attr.get_payload_as::<u128>()?))
@@ -85,6 +134,20 @@ impl<T: NlAttrType> PrimitiveAttrDecoder<T, u8> for u8 {
     }
 }
 
+impl<T: NlAttrType> PrimitiveAttrDecoder<T, TcpFlags> for TcpFlags {
+    fn decode(attr: &Nlattr<T, Buffer>) -> Result<TcpFlags> {
+        // The payload is a `struct nf_ct_tcp_flags { flags, mask }`, where only the
+        // flags are meaningful in kernel responses.
+        match attr.nla_payload().as_ref() {
+            [flags, _mask] => Ok(TcpFlags::from_bits_retain(*flags)),
+            payload => Err(Error::Netlink(format!(
+                "unexpected tcp flags length: {}",
+                payload.len()
+            ))),
+        }
+    }
+}
+
 impl<T: NlAttrType> PrimitiveAttrDecoder<T, Vec<String>> for StatusFlags {
     fn decode(attr: &Nlattr<T, Buffer>) -> Result<Vec<String>> {
         let status = u32::from_be(attr.get_payload_as::<u32>()?);
@@ -99,6 +162,109 @@ impl<T: NlAttrType> PrimitiveAttrDecoder<T, Vec<String>> for StatusFlags {
     }
 }
 
+impl Flow {
+    /// Decodes a `Flow` from a raw netlink message, i.e. the netlink header followed by
+    /// the netfilter header and the conntrack attributes, as read from a conntrack
+    /// socket or replayed from a capture. No socket is required.
+    ///
+    /// The origin and reply tuples are decoded independently of each other, so the
+    /// translated addresses and ports of NAT entries are kept as reported. The capture
+    /// below, taken on a little-endian host and reduced to its tuples, is a connection
+    /// from `192.168.1.10:40000` to `203.0.113.5:80` that was DNATed to `10.0.0.5:8080`
+    /// and SNATed to `198.51.100.1:61000`:
+    ///
+    /// ```rust
+    /// use conntrack::model::*;
+    ///
+    /// let raw = [
+    ///     0x7c, 0x00, 0x00, 0x00, 0x00, 0x01, 0x22, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ///     0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x34, 0x00, 0x01, 0x80,
+    ///     0x14, 0x00, 0x01, 0x80, 0x08, 0x00, 0x01, 0x00, 0xc0, 0xa8, 0x01, 0x0a,
+    ///     0x08, 0x00, 0x02, 0x00, 0xcb, 0x00, 0x71, 0x05, 0x1c, 0x00, 0x02, 0x80,
+    ///     0x05, 0x00, 0x01, 0x00, 0x06, 0x00, 0x00, 0x00, 0x06, 0x00, 0x02, 0x00,
+    ///     0x9c, 0x40, 0x00, 0x00, 0x06, 0x00, 0x03, 0x00, 0x00, 0x50, 0x00, 0x00,
+    ///     0x34, 0x00, 0x02, 0x80, 0x14, 0x00, 0x01, 0x80, 0x08, 0x00, 0x01, 0x00,
+    ///     0x0a, 0x00, 0x00, 0x05, 0x08, 0x00, 0x02, 0x00, 0xc6, 0x33, 0x64, 0x01,
+    ///     0x1c, 0x00, 0x02, 0x80, 0x05, 0x00, 0x01, 0x00, 0x06, 0x00, 0x00, 0x00,
+    ///     0x06, 0x00, 0x02, 0x00, 0x1f, 0x90, 0x00, 0x00, 0x06, 0x00, 0x03, 0x00,
+    ///     0xee, 0x48, 0x00, 0x00,
+    /// ];
+    /// let flow = Flow::from_bytes(&raw).unwrap();
+    ///
+    /// let origin = flow.origin.unwrap();
+    /// let origin_proto = origin.proto.unwrap();
+    /// assert_eq!(origin.src, Some("192.168.1.10".parse().unwrap()));
+    /// assert_eq!(origin.dst, Some("203.0.113.5".parse().unwrap()));
+    /// assert_eq!(origin_proto.src_port, Some(40000));
+    /// assert_eq!(origin_proto.dst_port, Some(80));
+    ///
+    /// let reply = flow.reply.unwrap();
+    /// let reply_proto = reply.proto.unwrap();
+    /// assert_eq!(reply.src, Some("10.0.0.5".parse().unwrap()));
+    /// assert_eq!(reply.dst, Some("198.51.100.1".parse().unwrap()));
+    /// assert_eq!(reply_proto.src_port, Some(8080));
+    /// assert_eq!(reply_proto.dst_port, Some(61000));
+    /// ```
+    ///
+    /// The kernel never repeats an attribute within a conntrack message or one of its
+    /// nests, so each field holds a single value. Should an attribute be repeated
+    /// anyway, the last instance wins, both here and in the accessors of `FlowRef`;
+    /// only `unknown_attrs` keeps every instance. Below, `CTA_MARK` is sent twice:
+    ///
+    /// ```rust
+    /// use conntrack::model::*;
+    /// use conntrack::view::*;
+    ///
+    /// let raw = [
+    ///     0x24, 0x00, 0x00, 0x00, 0x00, 0x01, 0x22, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ///     0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00,
+    ///     0x00, 0x00, 0x00, 0x01, 0x08, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02,
+    /// ];
+    ///
+    /// assert_eq!(Flow::from_bytes(&raw).unwrap().mark, Some(2));
+    /// assert_eq!(FlowRef::from_bytes(&raw).unwrap().mark(), Some(2));
+    /// ```
+    pub fn from_bytes(raw: &[u8]) -> Result<Flow> {
+        let message = Nlmsghdr::<CtNetlinkMessage, Genlmsghdr<u8, ConntrackAttr>>::from_bytes(
+            &mut Cursor::new(raw),
+        )?;
+
+        match message.nl_payload() {
+            NlPayload::Payload(payload) => Flow::decode_message(payload),
+            other => Err(Error::Netlink(format!(
+                "netlink message does not contain a conntrack entry: {other:?}"
+            ))),
+        }
+    }
+
+    /// Decodes every `Flow` from a raw buffer of consecutive netlink messages, such as a
+    /// recorded multipart dump. Control messages like `NLMSG_DONE` are skipped.
+    pub fn from_dump_bytes(raw: &[u8]) -> Result<Vec<Flow>> {
+        let mut cursor = Cursor::new(raw);
+        let mut flows = Vec::new();
+
+        while (cursor.position() as usize) < raw.len() {
+            let message = Nlmsghdr::<CtNetlinkMessage, Genlmsghdr<u8, ConntrackAttr>>::from_bytes(
+                &mut cursor,
+            )?;
+            if let NlPayload::Payload(payload) = message.nl_payload() {
+                flows.push(Flow::decode_message(payload)?);
+            }
+        }
+
+        Ok(flows)
+    }
+
+    /// Decodes the attributes of a conntrack message, taking the address family
+    /// from the netfilter header preceding them.
+    pub(crate) fn decode_message(message: &Genlmsghdr<u8, ConntrackAttr>) -> Result<Flow> {
+        let mut flow = Flow::decode(message.attrs().get_attr_handle())?;
+        flow.family = Some(*message.cmd());
+
+        Ok(flow)
+    }
+}
+
 impl<'a> AttrDecoder<'a, ConntrackAttr, Flow> for Flow {
     fn decode(attr_handle: CtAttrHandle<'a, ConntrackAttr>) -> Result<Flow> {
         let mut flow = Flow::default();
@@ -128,6 +294,11 @@ impl<'a> AttrDecoder<'a, ConntrackAttr, Flow> for Flow {
 
                     flow.reply = Some(IpTuple::decode(tuple_attr)?);
                 }
+                ConntrackAttr::CtaTupleMaster => {
+                    let tuple_attr = attr.get_attr_handle::<TupleAttr>()?;
+
+                    flow.master = Some(IpTuple::decode(tuple_attr)?);
+                }
                 ConntrackAttr::CtaCountersReply => {
                     let counter = attr.get_attr_handle::<CounterAttr>()?;
 
@@ -168,6 +339,11 @@ impl<'a> AttrDecoder<'a, ConntrackAttr, Flow> for Flow {
 
                     flow.sec_ctx = Some(SecCtx::decode(sec_ctx_attr)?);
                 }
+                ConntrackAttr::CtaSynProxy => {
+                    let synproxy_attr = attr.get_attr_handle::<SynProxyAttr>()?;
+
+                    flow.synproxy = Some(SynProxy::decode(synproxy_attr)?);
+                }
                 ConntrackAttr::CtaSecMark => {
                     flow.sec_mark = Some(u32::decode(attr)?);
                 }
@@ -182,13 +358,21 @@ impl<'a> AttrDecoder<'a, ConntrackAttr, Flow> for Flow {
 
                     flow.nat_src = Some(Nat::decode(nat_src_attr)?);
                 }
+                ConntrackAttr::CtaLabels => {
+                    flow.labels = Some(attr.nla_payload().as_ref().to_vec());
+                }
+                ConntrackAttr::CtaLabelsMask => {
+                    flow.labels_mask = Some(attr.nla_payload().as_ref().to_vec());
+                }
                 ConntrackAttr::CtaHelp => {
                     let helper_attr = attr.get_attr_handle::<HelperAttr>()?;
 
                     flow.helper = Some(Helper::decode(helper_attr)?);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
+                    flow.unknown_attrs
+                        .push((u16::from(*other), attr.nla_payload().as_ref().to_vec()));
                 }
             }
         }
@@ -220,7 +404,7 @@ impl<'a> AttrDecoder<'a, NatAttr, Nat> for Nat {
                     nat.ip_max = Some(IpAddr::decode_v6(inner_attr)?);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -246,7 +430,7 @@ impl<'a> AttrDecoder<'a, HelperAttr, Helper> for Helper {
                     helper.info = Some(info);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -267,7 +451,7 @@ impl<'a> AttrDecoder<'a, SecCtxAttr, SecCtx> for SecCtx {
                     sec_ctx.name = Some(name);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -276,6 +460,53 @@ impl<'a> AttrDecoder<'a, SecCtxAttr, SecCtx> for SecCtx {
     }
 }
 
+impl<'a> AttrDecoder<'a, SynProxyAttr, SynProxy> for SynProxy {
+    fn decode(attr_handle: CtAttrHandle<'a, SynProxyAttr>) -> Result<SynProxy> {
+        let mut synproxy = SynProxy::default();
+
+        for inner_attr in attr_handle.iter() {
+            match &inner_attr.nla_type().nla_type() {
+                SynProxyAttr::CtaSynProxyISN => {
+                    synproxy.isn = Some(u32::decode(inner_attr)?);
+                }
+                SynProxyAttr::CtaSynProxyITS => {
+                    synproxy.its = Some(u32::decode(inner_attr)?);
+                }
+                SynProxyAttr::CtaSynProxyTSOff => {
+                    synproxy.tsoff = Some(u32::decode(inner_attr)?);
+                }
+                other => {
+                    log::debug!("Failed to handle attribute: {other:?}");
+                }
+            }
+        }
+
+        Ok(synproxy)
+    }
+}
+
+impl<'a> AttrDecoder<'a, GlobalStatsAttr, GlobalStats> for GlobalStats {
+    fn decode(attr_handle: CtAttrHandle<'a, GlobalStatsAttr>) -> Result<GlobalStats> {
+        let mut stats = GlobalStats::default();
+
+        for attr in attr_handle.iter() {
+            match &attr.nla_type().nla_type() {
+                GlobalStatsAttr::CtaStatsGlobalEntries => {
+                    stats.entries = Some(u32::decode(attr)?);
+                }
+                GlobalStatsAttr::CtaStatsGlobalMaxEntries => {
+                    stats.max_entries = Some(u32::decode(attr)?);
+                }
+                other => {
+                    log::debug!("Failed to handle attribute: {other:?}");
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
 impl<'a> AttrDecoder<'a, SeqAdjAttr, SeqAdj> for SeqAdj {
     fn decode(attr_handle: CtAttrHandle<'a, SeqAdjAttr>) -> Result<SeqAdj> {
         let mut seq_adj = SeqAdj::default();
@@ -286,13 +517,13 @@ impl<'a> AttrDecoder<'a, SeqAdjAttr, SeqAdj> for SeqAdj {
                     seq_adj.correction_pos = Some(u32::decode(inner_attr)?);
                 }
                 SeqAdjAttr::CtaSeqAdjOffsetAfter => {
-                    seq_adj.offset_after = Some(u32::decode(inner_attr)?);
+                    seq_adj.offset_after = Some(u32::decode(inner_attr)? as i32);
                 }
                 SeqAdjAttr::CtaSeqAdjOffsetBefore => {
-                    seq_adj.offset_before = Some(u32::decode(inner_attr)?);
+                    seq_adj.offset_before = Some(u32::decode(inner_attr)? as i32);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -319,7 +550,7 @@ impl<'a> AttrDecoder<'a, ProtoInfoAttr, ProtoInfo> for ProtoInfo {
                     proto_info.sctp = Some(SctpInfo::decode(sctp_info_attr)?);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -338,24 +569,10 @@ impl<'a> AttrDecoder<'a, TcpInfoAttr, TcpInfo> for TcpInfo {
                     tcp_info.state = Some(TcpState::from(u8::decode(inner_attr)?));
                 }
                 TcpInfoAttr::CtaProtoInfoTcpFlagsOriginal => {
-                    let bytes = Vec::<u8>::from(inner_attr.nla_payload().as_ref());
-                    if bytes.len() != 2 {
-                        let flags = TcpFlags {
-                            flags: Some(bytes[0]),
-                            mask: Some(bytes[1]),
-                        };
-                        tcp_info.flags_orig = Some(flags);
-                    }
+                    tcp_info.flags_orig = Some(TcpFlags::decode(inner_attr)?);
                 }
                 TcpInfoAttr::CtaProtoInfoTcpFlagsReply => {
-                    let bytes = Vec::<u8>::from(inner_attr.nla_payload().as_ref());
-                    if bytes.len() != 2 {
-                        let flags = TcpFlags {
-                            flags: Some(bytes[0]),
-                            mask: Some(bytes[1]),
-                        };
-                        tcp_info.flags_reply = Some(flags);
-                    }
+                    tcp_info.flags_reply = Some(TcpFlags::decode(inner_attr)?);
                 }
                 TcpInfoAttr::CtaProtoInfoTcpWScaleOriginal => {
                     tcp_info.wscale_orig = Some(u8::decode(inner_attr)?);
@@ -364,7 +581,7 @@ impl<'a> AttrDecoder<'a, TcpInfoAttr, TcpInfo> for TcpInfo {
                     tcp_info.wscale_repl = Some(u8::decode(inner_attr)?);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -390,7 +607,7 @@ impl<'a> AttrDecoder<'a, DccpInfoAttr, DccpInfo> for DccpInfo {
                     dccp_info.handshake_seq = Some(u64::decode(inner_attr)?);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -416,7 +633,7 @@ impl<'a> AttrDecoder<'a, SctpInfoAttr, SctpInfo> for SctpInfo {
                     sctp_info.vtag_reply = Some(u32::decode(inner_attr)?);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -440,7 +657,7 @@ impl<'a> AttrDecoder<'a, TimestampAttr, Timestamp> for Timestamp {
                     timestamp.end = Some(Utc.timestamp_nanos(ts_end as i64));
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -449,6 +666,16 @@ impl<'a> AttrDecoder<'a, TimestampAttr, Timestamp> for Timestamp {
     }
 }
 
+/// Decodes a counter by the length of its payload rather than its attribute type,
+/// as both 32 and 64 bit counters have been sent under either type over time.
+fn decode_counter(attr: &Nlattr<CounterAttr, Buffer>) -> Result<u64> {
+    match attr.nla_payload().len() {
+        4 => Ok(u64::from(u32::decode(attr)?)),
+        8 => u64::decode(attr),
+        len => Err(Error::Netlink(format!("unexpected counter length: {len}"))),
+    }
+}
+
 impl<'a> AttrDecoder<'a, CounterAttr, Counter> for Counter {
     fn decode(attr_handle: CtAttrHandle<'a, CounterAttr>) -> Result<Counter> {
         let mut counter = Counter::default();
@@ -456,21 +683,24 @@ impl<'a> AttrDecoder<'a, CounterAttr, Counter> for Counter {
         for inner_attr in attr_handle.iter() {
             match &inner_attr.nla_type().nla_type() {
                 CounterAttr::CtaCountersPackets => {
-                    counter.packets = Some(u64::decode(inner_attr)?);
+                    counter.packets = Some(decode_counter(inner_attr)?);
                 }
                 CounterAttr::CtaCountersBytes => {
-                    counter.bytes = Some(u64::decode(inner_attr)?);
+                    counter.bytes = Some(decode_counter(inner_attr)?);
                 }
+                // The 32 bit variants never override their 64 bit counterparts,
+                // whatever the order they are received in.
                 CounterAttr::CtaCountersPackets32 => {
-                    let packets = u32::decode(inner_attr)?;
-                    counter.packets = Some(packets as u64);
+                    let packets = decode_counter(inner_attr)?;
+                    counter.packets.get_or_insert(packets);
                 }
                 CounterAttr::CtaCountersBytes32 => {
-                    let bytes = u32::decode(inner_attr)?;
-                    counter.bytes = Some(bytes as u64);
+                    let bytes = decode_counter(inner_attr)?;
+                    counter.bytes.get_or_insert(bytes);
                 }
+                CounterAttr::CtaCountersPad => {}
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -497,7 +727,7 @@ impl<'a> AttrDecoder<'a, TupleAttr, IpTuple> for IpTuple {
                     ip_tuple.zone = Some(u16::decode(inner_attr)?);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -525,7 +755,7 @@ impl<'a> AttrDecoder<'a, IpTupleAttr, SrcDst> for SrcDst {
                     src_dst.1 = Some(IpAddr::decode_v6(ip_inner)?);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -568,7 +798,7 @@ impl<'a> AttrDecoder<'a, ProtoTupleAttr, ProtoTuple> for ProtoTuple {
                     tuple.icmpv6_code = Some(u8::decode(attr)?);
                 }
                 other => {
-                    log::warn!("Failed to handle attribute: {other:?}");
+                    log::debug!("Failed to handle attribute: {other:?}");
                 }
             }
         }
@@ -576,3 +806,59 @@ impl<'a> AttrDecoder<'a, ProtoTupleAttr, ProtoTuple> for ProtoTuple {
         Ok(tuple)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::view::FlowRef;
+
+    /// A conntrack entry of an ICMPv6 echo request, id 0x1234, from 2001:db8::1 to
+    /// 2001:db8::2, with the netlink header in little-endian byte order.
+    const ICMPV6_ECHO: &str = "\
+        680000000001000000000000000000000a000000540001802c00018014000300\
+        20010db80000000000000000000000011400040020010db80000000000000000\
+        0000000224000280050001003a00000006000700123400000500080080000000\
+        0500090000000000";
+
+    /// An IPv4 conntrack entry with only `CTA_MARK` 5 and `CTA_SECMARK` 9 set.
+    const MARKED: &str = "240000000001000000000000000000000200000008000800000000050800110000000009";
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn decode_icmpv6_echo() {
+        let flow = Flow::from_bytes(&hex(ICMPV6_ECHO)).unwrap();
+        assert_eq!(flow.family, Some(libc::AF_INET6 as u8));
+
+        let origin = flow.origin.unwrap();
+        assert_eq!(origin.src, Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(origin.dst, Some("2001:db8::2".parse().unwrap()));
+
+        let proto = origin.proto.unwrap();
+        assert_eq!(proto.number, Some(IpProto::Icmpv6));
+        assert_eq!(proto.icmpv6_id, Some(0x1234));
+        assert_eq!(proto.icmpv6_type, Some(128));
+        assert_eq!(proto.icmpv6_code, Some(0));
+        assert_eq!(proto.icmp_id, None);
+        assert_eq!(proto.icmp_type, None);
+        assert_eq!(proto.icmp_code, None);
+    }
+
+    #[test]
+    fn decode_mark_and_sec_mark() {
+        let raw = hex(MARKED);
+
+        let flow = Flow::from_bytes(&raw).unwrap();
+        assert_eq!(flow.mark, Some(5));
+        assert_eq!(flow.sec_mark, Some(9));
+
+        let view = FlowRef::from_bytes(&raw).unwrap();
+        assert_eq!(view.mark(), Some(5));
+        assert_eq!(view.sec_mark(), Some(9));
+    }
+}
@@ -1,5 +1,33 @@
-use neli::{Size, ToBytes, genl::Nlattr, types::Buffer};
+//! # Encoders
+//! This module contains encoder traits and implementations capable of converting
+//! conntrack models into neli attributes for conntrack subsystem requests.
+
+use bitflags::bitflags;
+use neli::{
+    Size, ToBytes,
+    consts::genl::NlAttrType,
+    genl::{AttrTypeBuilder, Nlattr, NlattrBuilder},
+    types::{Buffer, GenlBuffer},
+};
 use std::io::Cursor;
+use std::net::IpAddr;
+
+use crate::attributes::*;
+use crate::model::*;
+use crate::result::*;
+
+bitflags! {
+    /// The `CTA_FILTER_FLAG_*` values selecting which fields of the tuples in a dump
+    /// request the kernel compares against.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    struct FilterFlags: u32 {
+        const IP_SRC = 1 << 0;
+        const IP_DST = 1 << 1;
+        const PROTO_NUM = 1 << 3;
+        const PROTO_SRC_PORT = 1 << 4;
+        const PROTO_DST_PORT = 1 << 5;
+    }
+}
 
 pub trait IntoBuffer {
     fn into_buffer(self) -> Buffer;
@@ -21,3 +49,412 @@ where
         Buffer::from(cursor.into_inner())
     }
 }
+
+impl IntoBuffer for u8 {
+    fn into_buffer(self) -> Buffer {
+        Buffer::from(vec![self])
+    }
+}
+
+impl IntoBuffer for u16 {
+    fn into_buffer(self) -> Buffer {
+        Buffer::from(self.to_be_bytes().to_vec())
+    }
+}
+
+impl IntoBuffer for u32 {
+    fn into_buffer(self) -> Buffer {
+        Buffer::from(self.to_be_bytes().to_vec())
+    }
+}
+
+impl IntoBuffer for u64 {
+    fn into_buffer(self) -> Buffer {
+        Buffer::from(self.to_be_bytes().to_vec())
+    }
+}
+
+/// The attribute encoder trait is implemented to convert a conntrack model into a
+/// single nested attribute of the given type. This is the counterpart of the
+/// `AttrDecoder` trait.
+pub trait AttrEncoder<T>
+where
+    T: NlAttrType,
+{
+    fn encode(&self, attr_type: T) -> Result<Nlattr<T, Buffer>>;
+}
+
+/// The message encoder trait is implemented to convert a conntrack model into the
+/// top level attributes of a conntrack request.
+pub trait MessageEncoder<T>
+where
+    T: NlAttrType,
+{
+    fn encode(&self) -> Result<GenlBuffer<T, Buffer>>;
+}
+
+/// Tuples are encoded with the same attributes they are decoded from, so an encoded
+/// tuple of either address family decodes back into an equal `IpTuple`:
+///
+/// ```rust
+/// use conntrack::attributes::*;
+/// use conntrack::decoders::*;
+/// use conntrack::encoders::*;
+/// use conntrack::model::*;
+/// use neli::{FromBytes, ToBytes, genl::Nlattr, types::Buffer};
+/// use std::io::Cursor;
+///
+/// for (src, dst) in [("192.168.1.10", "203.0.113.5"), ("2001:db8::1", "2001:db8::2")] {
+///     let tuple = IpTuple {
+///         src: Some(src.parse().unwrap()),
+///         dst: Some(dst.parse().unwrap()),
+///         proto: Some(ProtoTuple {
+///             number: Some(IpProto::Tcp),
+///             src_port: Some(40000),
+///             dst_port: Some(443),
+///             ..Default::default()
+///         }),
+///         zone: Some(7),
+///     };
+///
+///     let mut raw = Cursor::new(Vec::new());
+///     tuple.encode(ConntrackAttr::CtaTupleOrig)?.to_bytes(&mut raw)?;
+///     let attr = Nlattr::<ConntrackAttr, Buffer>::from_bytes(&mut Cursor::new(raw.into_inner()))?;
+///
+///     assert_eq!(IpTuple::decode(attr.get_attr_handle::<TupleAttr>()?)?, tuple);
+/// }
+/// # Ok::<(), conntrack::Error>(())
+/// ```
+impl<T: NlAttrType> AttrEncoder<T> for IpTuple {
+    fn encode(&self, attr_type: T) -> Result<Nlattr<T, Buffer>> {
+        let mut tuple = make_attr(attr_type, true, Buffer::new())?;
+
+        if let (Some(src), Some(dst)) = (self.src, self.dst)
+            && src.is_ipv4() != dst.is_ipv4()
+        {
+            return Err(Error::InvalidTuple("src and dst address families differ"));
+        }
+        if self.src.is_some() || self.dst.is_some() {
+            let mut ip_tuple = make_attr(TupleAttr::CtaTupleIp, true, Buffer::new())?;
+            if let Some(src) = self.src {
+                let src_attr = ip_attr(src, IpTupleAttr::CtaIpv4Src, IpTupleAttr::CtaIpv6Src)?;
+                ip_tuple = ip_tuple.nest(&src_attr)?;
+            }
+            if let Some(dst) = self.dst {
+                let dst_attr = ip_attr(dst, IpTupleAttr::CtaIpv4Dst, IpTupleAttr::CtaIpv6Dst)?;
+                ip_tuple = ip_tuple.nest(&dst_attr)?;
+            }
+            tuple = tuple.nest(&ip_tuple)?;
+        }
+
+        if let Some(proto) = &self.proto {
+            tuple = tuple.nest(&proto.encode(TupleAttr::CtaTupleProto)?)?;
+        }
+        if let Some(zone) = self.zone {
+            tuple = tuple.nest(&make_attr(
+                TupleAttr::CtaTupleZone,
+                false,
+                zone.into_buffer(),
+            )?)?;
+        }
+
+        Ok(tuple)
+    }
+}
+
+impl AttrEncoder<TupleAttr> for ProtoTuple {
+    fn encode(&self, attr_type: TupleAttr) -> Result<Nlattr<TupleAttr, Buffer>> {
+        let mut proto = make_attr(attr_type, true, Buffer::new())?;
+
+        let fields = [
+            (
+                ProtoTupleAttr::CtaProtoNum,
+                self.number.map(|n| u8::from(n).into_buffer()),
+            ),
+            (
+                ProtoTupleAttr::CtaProtoSrcPort,
+                self.src_port.map(u16::into_buffer),
+            ),
+            (
+                ProtoTupleAttr::CtaProtoDstPort,
+                self.dst_port.map(u16::into_buffer),
+            ),
+            (
+                ProtoTupleAttr::CtaProtoIcmpId,
+                self.icmp_id.map(u16::into_buffer),
+            ),
+            (
+                ProtoTupleAttr::CtaProtoIcmpType,
+                self.icmp_type.map(u8::into_buffer),
+            ),
+            (
+                ProtoTupleAttr::CtaProtoIcmpCode,
+                self.icmp_code.map(u8::into_buffer),
+            ),
+            (
+                ProtoTupleAttr::CtaProtoIcmpV6Id,
+                self.icmpv6_id.map(u16::into_buffer),
+            ),
+            (
+                ProtoTupleAttr::CtaProtoIcmpV6Type,
+                self.icmpv6_type.map(u8::into_buffer),
+            ),
+            (
+                ProtoTupleAttr::CtaProtoIcmpV6Code,
+                self.icmpv6_code.map(u8::into_buffer),
+            ),
+        ];
+        for (proto_type, payload) in fields {
+            if let Some(payload) = payload {
+                proto = proto.nest(&make_attr(proto_type, false, payload)?)?;
+            }
+        }
+
+        Ok(proto)
+    }
+}
+
+impl<T: NlAttrType> AttrEncoder<T> for Tuple {
+    fn encode(&self, attr_type: T) -> Result<Nlattr<T, Buffer>> {
+        self.validate()?;
+        IpTuple::from(self).encode(attr_type)
+    }
+}
+
+impl MessageEncoder<ConntrackAttr> for NewFlow {
+    fn encode(&self) -> Result<GenlBuffer<ConntrackAttr, Buffer>> {
+        let mut attrs = GenlBuffer::<ConntrackAttr, Buffer>::new();
+        attrs.push(self.origin.encode(ConntrackAttr::CtaTupleOrig)?);
+        attrs.push(self.reply.encode(ConntrackAttr::CtaTupleReply)?);
+
+        let timeout = self.timeout.as_secs() as u32;
+        attrs.push(make_attr(
+            ConntrackAttr::CtaTimeout,
+            false,
+            timeout.into_buffer(),
+        )?);
+        if let Some(zone) = self.zone {
+            attrs.push(make_attr(
+                ConntrackAttr::CtaZone,
+                false,
+                zone.into_buffer(),
+            )?);
+        }
+        if let Some(mark) = self.mark {
+            attrs.push(make_attr(
+                ConntrackAttr::CtaMark,
+                false,
+                mark.into_buffer(),
+            )?);
+        }
+        // The kernel confirms new entries before applying the status and rejects a
+        // status that would clear the confirmed flag again.
+        if let Some(status) = self.status {
+            let status = status | StatusFlags::StatusConfirmed;
+            attrs.push(make_attr(
+                ConntrackAttr::CtaStatus,
+                false,
+                status.bits().into_buffer(),
+            )?);
+        }
+        if let Some(helper) = &self.helper {
+            let mut name = helper.as_bytes().to_vec();
+            name.push(0);
+            let help = make_attr(ConntrackAttr::CtaHelp, true, Buffer::new())?;
+            attrs.push(help.nest(&make_attr(
+                HelperAttr::CtaHelpName,
+                false,
+                Buffer::from(name),
+            )?)?);
+        }
+
+        Ok(attrs)
+    }
+}
+
+impl MessageEncoder<ExpectAttr> for NewExpectation {
+    fn encode(&self) -> Result<GenlBuffer<ExpectAttr, Buffer>> {
+        let mut attrs = GenlBuffer::<ExpectAttr, Buffer>::new();
+        attrs.push(self.master.encode(ExpectAttr::CtaExpectMaster)?);
+        attrs.push(self.tuple.encode(ExpectAttr::CtaExpectTuple)?);
+        attrs.push(self.mask.encode(ExpectAttr::CtaExpectMask)?);
+
+        let timeout = self.timeout.as_secs() as u32;
+        attrs.push(make_attr(
+            ExpectAttr::CtaExpectTimeout,
+            false,
+            timeout.into_buffer(),
+        )?);
+        if let Some(zone) = self.zone {
+            attrs.push(make_attr(
+                ExpectAttr::CtaExpectZone,
+                false,
+                zone.into_buffer(),
+            )?);
+        }
+        if !self.flags.is_empty() {
+            attrs.push(make_attr(
+                ExpectAttr::CtaExpectFlags,
+                false,
+                self.flags.bits().into_buffer(),
+            )?);
+        }
+        if let Some(helper) = &self.helper {
+            let mut name = helper.as_bytes().to_vec();
+            name.push(0);
+            attrs.push(make_attr(
+                ExpectAttr::CtaExpectHelpName,
+                false,
+                Buffer::from(name),
+            )?);
+        }
+
+        Ok(attrs)
+    }
+}
+
+impl MessageEncoder<ConntrackAttr> for DumpFilter {
+    fn encode(&self) -> Result<GenlBuffer<ConntrackAttr, Buffer>> {
+        let mut attrs = GenlBuffer::<ConntrackAttr, Buffer>::new();
+        if let Some((flags, mask)) = self.status {
+            attrs.push(make_attr(
+                ConntrackAttr::CtaStatus,
+                false,
+                flags.bits().into_buffer(),
+            )?);
+            attrs.push(make_attr(
+                ConntrackAttr::CtaStatusMask,
+                false,
+                mask.bits().into_buffer(),
+            )?);
+        }
+
+        if let Some((mark, mask)) = self.mark {
+            attrs.push(make_attr(
+                ConntrackAttr::CtaMark,
+                false,
+                mark.into_buffer(),
+            )?);
+            attrs.push(make_attr(
+                ConntrackAttr::CtaMarkMask,
+                false,
+                mask.into_buffer(),
+            )?);
+        }
+
+        if let Some(zone) = self.zone {
+            attrs.push(make_attr(
+                ConntrackAttr::CtaZone,
+                false,
+                zone.into_buffer(),
+            )?);
+        }
+
+        let mut orig_flags = FilterFlags::empty();
+        let mut orig = IpTuple {
+            src: self.src,
+            dst: self.dst,
+            ..Default::default()
+        };
+        if self.src.is_some() {
+            orig_flags |= FilterFlags::IP_SRC;
+        }
+        if self.dst.is_some() {
+            orig_flags |= FilterFlags::IP_DST;
+        }
+        if let Some(proto) = self.proto {
+            orig_flags |= FilterFlags::PROTO_NUM;
+            orig.proto = Some(ProtoTuple {
+                number: Some(proto),
+                ..Default::default()
+            });
+        }
+        if !orig_flags.is_empty() {
+            attrs.push(orig.encode(ConntrackAttr::CtaTupleOrig)?);
+            // The filter flags are host byte order, unlike the other attributes.
+            let flags = Buffer::from(orig_flags.bits().to_ne_bytes().to_vec());
+            let filter = make_attr(ConntrackAttr::CtaFilter, true, Buffer::new())?
+                .nest(&make_attr(FilterAttr::CtaFilterOrigFlags, false, flags)?)?;
+            attrs.push(filter);
+        }
+
+        Ok(attrs)
+    }
+}
+
+fn ip_attr<T>(ip: IpAddr, v4_type: T, v6_type: T) -> Result<Nlattr<T, Buffer>>
+where
+    T: NlAttrType,
+{
+    match ip {
+        IpAddr::V4(ip) => make_attr(v4_type, false, Buffer::from(ip.octets().to_vec())),
+        IpAddr::V6(ip) => make_attr(v6_type, false, Buffer::from(ip.octets().to_vec())),
+    }
+}
+
+pub(crate) fn make_attr<T, P>(attr_type: T, nest: bool, payload: P) -> Result<Nlattr<T, Buffer>>
+where
+    P: Size + ToBytes,
+    T: NlAttrType,
+{
+    Ok(NlattrBuilder::default()
+        .nla_type(
+            AttrTypeBuilder::default()
+                .nla_type(attr_type)
+                .nla_nested(nest)
+                .build()?,
+        )
+        .nla_payload(payload)
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lists the type and payload of the top-level attributes of a request.
+    fn attrs(buffer: &GenlBuffer<ConntrackAttr, Buffer>) -> Vec<(ConntrackAttr, Vec<u8>)> {
+        buffer
+            .iter()
+            .map(|attr| {
+                (
+                    *attr.nla_type().nla_type(),
+                    attr.nla_payload().as_ref().to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dump_filter_encodes_mark() {
+        let filter = DumpFilter::default().mark(0x1230, 0xfff0);
+
+        assert_eq!(
+            attrs(&filter.encode().unwrap()),
+            [
+                (ConntrackAttr::CtaMark, 0x1230u32.to_be_bytes().to_vec()),
+                (ConntrackAttr::CtaMarkMask, 0xfff0u32.to_be_bytes().to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn dump_filter_encodes_status() {
+        let assured = StatusFlags::StatusAssured;
+        let filter = DumpFilter::default().status(assured, assured);
+
+        assert_eq!(
+            attrs(&filter.encode().unwrap()),
+            [
+                (
+                    ConntrackAttr::CtaStatus,
+                    assured.bits().to_be_bytes().to_vec()
+                ),
+                (
+                    ConntrackAttr::CtaStatusMask,
+                    assured.bits().to_be_bytes().to_vec()
+                ),
+            ]
+        );
+    }
+}
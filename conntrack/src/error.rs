@@ -3,6 +3,7 @@
 //! the `conntrack` library.
 
 use std::fmt::Debug;
+use std::io;
 
 /// Error consolidates and propagates all underlying error types.
 #[derive(thiserror::Error, Debug)]
@@ -10,6 +11,45 @@ pub enum Error {
     #[error("netlink error: {0}")]
     Netlink(String),
 
+    #[error("not subscribed to any conntrack event groups")]
+    NotSubscribed,
+
+    #[error("flow is missing the {0} field")]
+    IncompleteFlow(&'static str),
+
+    #[error("invalid tuple: {0}")]
+    InvalidTuple(&'static str),
+
+    #[error("deleted {deleted} entries, {} deletions failed", .errors.len())]
+    PartialDelete { deleted: u32, errors: Vec<Error> },
+
+    #[error("the entry already exists")]
+    AlreadyExists,
+
+    #[error("timed out waiting for the kernel")]
+    Timeout,
+
+    #[error("dump ended without NLMSG_DONE, the received flows are incomplete")]
+    Truncated,
+
+    #[error("table changed during the dump, the received flows are inconsistent")]
+    DumpInterrupted,
+
+    #[error("event socket overrun, {} events dropped", .dropped.map_or("unknown number of".to_string(), |d| d.to_string()))]
+    Overrun { dropped: Option<u32> },
+
+    #[error("attribute payload is {len} bytes, expected {expected}")]
+    BadAttrLen { expected: usize, len: usize },
+
+    #[error("invalid filter argument: {0}")]
+    InvalidFilter(String),
+
+    #[error("invalid connlabels: {0}")]
+    InvalidLabels(&'static str),
+
+    #[error("status flags {0:?} cannot be set from userspace")]
+    InvalidStatus(crate::model::StatusFlags),
+
     #[error(transparent)]
     IO(#[from] std::io::Error),
 
@@ -35,8 +75,45 @@ pub enum Error {
     GenlattrTypeBuilder(#[from] neli::genl::AttrTypeBuilderError),
 }
 
+impl Error {
+    /// Returns the errno carried by the error, either reported by the kernel in a
+    /// netlink error message or by the underlying socket.
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            Error::IO(e) => e.raw_os_error(),
+            Error::Socket(neli::err::SocketError::Io(e)) => e.raw_os_error(),
+            Error::Overrun { .. } => Some(libc::ENOBUFS),
+            Error::AlreadyExists => Some(libc::EEXIST),
+            Error::Timeout => Some(libc::EAGAIN),
+            Error::DumpInterrupted => Some(libc::EINTR),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` for the errno values that indicate a transient condition,
+    /// after which the failed operation may succeed when retried.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self.errno(),
+            Some(libc::EINTR | libc::EAGAIN | libc::ENOBUFS)
+        )
+    }
+}
+
 impl<T: Debug, P: Debug> From<neli::err::RouterError<T, P>> for Error {
     fn from(value: neli::err::RouterError<T, P>) -> Self {
-        Self::Netlink(format!("{value:?}"))
+        match value {
+            neli::err::RouterError::Nlmsgerr(e) => {
+                Self::IO(io::Error::from_raw_os_error(-e.error()))
+            }
+            neli::err::RouterError::Socket(neli::err::SocketError::Io(e)) => {
+                match e.raw_os_error() {
+                    Some(errno) => Self::IO(io::Error::from_raw_os_error(errno)),
+                    None => Self::IO(io::Error::new(e.kind(), e.to_string())),
+                }
+            }
+            neli::err::RouterError::Io(kind) => Self::IO(kind.into()),
+            value => Self::Netlink(format!("{value:?}")),
+        }
     }
 }
@@ -58,8 +58,14 @@ pub mod attributes;
 pub mod decoders;
 pub mod encoders;
 pub mod message;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod model;
+pub mod table;
+pub mod view;
 
 mod connection;
+#[cfg(feature = "csv")]
+mod csv;
 mod error;
 mod result;
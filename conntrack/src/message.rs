@@ -8,8 +8,14 @@ const fn subsys_message(subsys: CtNetlinkSubsys, msg: CtMessage) -> u16 {
     ((subsys as u16) << 8) | (msg as u16)
 }
 
+#[inline]
+const fn exp_message(msg: CtExpMessage) -> u16 {
+    ((CtNetlinkSubsys::CtNetlinkExp as u16) << 8) | (msg as u16)
+}
+
 #[repr(u8)]
 #[allow(unused)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CtNetlinkSubsys {
     CtNetlink = libc::NFNL_SUBSYS_CTNETLINK as u8,
     CtNetlinkExp = libc::NFNL_SUBSYS_CTNETLINK_EXP as u8,
@@ -28,10 +34,31 @@ pub enum CtMessage {
     CtGetUnconfirmed = 7u8,
 }
 
+#[repr(u8)]
+pub enum CtExpMessage {
+    ExpNew = 0u8,
+    ExpGet = 1u8,
+    ExpDelete = 2u8,
+}
+
 #[neli_enum(serialized_type = "u16")]
 pub enum CtNetlinkMessage {
+    CtNew = subsys_message(CtNetlinkSubsys::CtNetlink, CtMessage::CtNew),
     Conntrack = subsys_message(CtNetlinkSubsys::CtNetlink, CtMessage::CtGet),
     CtDelete = subsys_message(CtNetlinkSubsys::CtNetlink, CtMessage::CtDelete),
+    CtGetCtrZero = subsys_message(CtNetlinkSubsys::CtNetlink, CtMessage::CtGetCtrZero),
+    CtGetStats = subsys_message(CtNetlinkSubsys::CtNetlink, CtMessage::CtGetStats),
 }
 
 impl neli::consts::nl::NlType for CtNetlinkMessage {}
+
+/// The messages of the expectation subsystem, `NFNL_SUBSYS_CTNETLINK_EXP`, used to
+/// send expectation requests.
+#[neli_enum(serialized_type = "u16")]
+pub enum CtNetlinkMessageExp {
+    ExpNew = exp_message(CtExpMessage::ExpNew),
+    ExpGet = exp_message(CtExpMessage::ExpGet),
+    ExpDelete = exp_message(CtExpMessage::ExpDelete),
+}
+
+impl neli::consts::nl::NlType for CtNetlinkMessageExp {}
@@ -0,0 +1,83 @@
+//! # Metrics
+//! This module contains aggregated metrics of the conntrack table, along with their
+//! rendering in the Prometheus text exposition format. It requires the `metrics`
+//! feature.
+
+use std::fmt::Write;
+
+use crate::table::*;
+
+/// The `ConntrackMetrics` type holds the number of conntrack entries, broken down
+/// by protocol and TCP state, as returned by `Conntrack::collect_metrics()`.
+#[derive(Clone, Default, Debug)]
+pub struct ConntrackMetrics {
+    /// The entry counts of the table.
+    pub summary: Summary,
+    /// The maximum number of entries of the table, `net.netfilter.nf_conntrack_max`.
+    pub max_entries: Option<u32>,
+}
+
+impl ConntrackMetrics {
+    /// Renders the metrics in the Prometheus text exposition format, ready to be
+    /// served on a `/metrics` endpoint.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        gauge(
+            &mut out,
+            "conntrack_entries",
+            "Number of entries in the conntrack table.",
+        );
+        _ = writeln!(out, "conntrack_entries {}", self.summary.total);
+        if let Some(max_entries) = self.max_entries {
+            gauge(
+                &mut out,
+                "conntrack_max_entries",
+                "Maximum number of entries in the conntrack table.",
+            );
+            _ = writeln!(out, "conntrack_max_entries {max_entries}");
+        }
+
+        gauge(
+            &mut out,
+            "conntrack_protocol_entries",
+            "Number of conntrack entries per transport protocol.",
+        );
+        for (proto, count) in &self.summary.protocols {
+            _ = writeln!(
+                out,
+                "conntrack_protocol_entries{{protocol=\"{proto}\"}} {count}"
+            );
+        }
+
+        gauge(
+            &mut out,
+            "conntrack_tcp_state_entries",
+            "Number of TCP conntrack entries per TCP state.",
+        );
+        for (state, count) in &self.summary.tcp_states {
+            _ = writeln!(
+                out,
+                "conntrack_tcp_state_entries{{state=\"{state}\"}} {count}"
+            );
+        }
+
+        out
+    }
+}
+
+impl From<Summary> for ConntrackMetrics {
+    /// Builds metrics from a summary, e.g. of a `FlowTable`, without the maximum
+    /// table size.
+    fn from(summary: Summary) -> Self {
+        ConntrackMetrics {
+            summary,
+            max_entries: None,
+        }
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str) {
+    _ = writeln!(out, "# HELP {name} {help}");
+    _ = writeln!(out, "# TYPE {name} gauge");
+}
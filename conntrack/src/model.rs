@@ -5,7 +5,11 @@ use bitflags::bitflags;
 use chrono::prelude::*;
 use neli::neli_enum;
 use serde::{Deserialize, Serialize};
-use std::{net, time::Duration};
+use std::{fmt, net, time::Duration};
+
+use crate::attributes::ConntrackAttr;
+use crate::error::Error;
+use crate::message::CtNetlinkSubsys;
 
 /// The `Flow` type contains all the information of a connection dumped from the
 /// conntrack table. Note that the `Flow` type can be used to support multiple
@@ -16,12 +20,18 @@ use std::{net, time::Duration};
 pub struct Flow {
     /// Unique id assigned to this conntrack entry.
     pub id: Option<u32>,
+    /// The address family of the entry, `libc::AF_INET` or `libc::AF_INET6`, as
+    /// reported by the `nfgen_family` field of the netfilter header.
+    pub family: Option<u8>,
     /// The origin of the network traffic, containing the `src` address and `sport`, the `dst`
     /// address and `dport`, and protocol information.
     pub origin: Option<IpTuple>,
     /// The reply of the network traffic, containing the `src` address and `sport`, the `dst`
     /// address and `dport`, and protocol information.
     pub reply: Option<IpTuple>,
+    /// The origin tuple of the master connection for entries created by a helper
+    /// expectation, e.g. an FTP data connection pointing back to its control channel.
+    pub master: Option<IpTuple>,
     /// Metadata specific to the protocol being used to facilitate the network transfer.
     pub proto_info: Option<ProtoInfo>,
     /// Byte and packet counter data relative to the traffic origin. Enable with `sysctl
@@ -38,18 +48,224 @@ pub struct Flow {
     /// Use is a reference count for the connection used internally for garbage collection.
     pub entry_use: Option<u32>,
     pub zone: Option<u16>,
+    /// The connection mark, `CTA_MARK`, as set by the iptables `CONNMARK` target.
     pub mark: Option<u32>,
     pub mark_mask: Option<u32>,
     pub timestamp: Option<Timestamp>,
     pub status_mask: Option<u32>,
     pub helper: Option<Helper>,
     pub nat_src: Option<Nat>,
+    /// The TCP sequence number adjustment of the original direction, `CTA_SEQ_ADJ_ORIG`,
+    /// present once a helper like the FTP NAT helper changed a payload length.
     pub seq_adj_orig: Option<SeqAdj>,
+    /// The TCP sequence number adjustment of the reply direction, `CTA_SEQ_ADJ_REPLY`.
     pub seq_adj_repl: Option<SeqAdj>,
     pub sec_ctx: Option<SecCtx>,
+    /// The numeric security mark, `CTA_SECMARK`, as set by the iptables `CONNSECMARK`
+    /// target. It is unrelated to `mark`, and to the context name in `sec_ctx`.
     pub sec_mark: Option<u32>,
+    /// The SYNPROXY state, only present for connections whose handshake was
+    /// completed by the nftables or iptables `SYNPROXY` target.
+    pub synproxy: Option<SynProxy>,
     pub exp: Option<Exp>,
+    /// The raw connlabel bitmap, as set by an iptables `connlabel` rule or with
+    /// `Conntrack::set_labels()`. See `has_label()` for testing single labels.
+    pub labels: Option<Vec<u8>>,
+    /// The connlabel bits managed by the sender, `CTA_LABELS_MASK`: a clear bit of
+    /// `labels` is only meaningful where the mask bit is set. The kernel accepts the
+    /// mask on updates but doesn't include it in its replies, so this is only
+    /// present for messages built by other userspace tools.
+    pub labels_mask: Option<Vec<u8>>,
+    /// The type and raw payload of every top-level attribute the decoder doesn't
+    /// handle, e.g. ones added by newer kernels, in the order they were received.
+    #[serde(default)]
+    pub unknown_attrs: Vec<(u16, Vec<u8>)>,
+}
+
+impl Flow {
+    /// Returns `true` if the connlabel with the given bit number, e.g. the `N` of
+    /// `connlabel --label N`, is set. The kernel stores the bitmap as native `long`
+    /// words, which are walked accordingly.
+    pub fn has_label(&self, bit: usize) -> bool {
+        const WORD_SIZE: usize = std::mem::size_of::<usize>();
+        let bits = usize::BITS as usize;
+
+        let start = bit / bits * WORD_SIZE;
+        self.labels
+            .as_ref()
+            .and_then(|labels| labels.get(start..start + WORD_SIZE))
+            .and_then(|word| word.try_into().ok())
+            .is_some_and(|word| (usize::from_ne_bytes(word) >> (bit % bits)) & 1 == 1)
+    }
+
+    /// Returns the tuple of the given direction, `origin` or `reply`.
+    pub fn tuple(&self, direction: Direction) -> Option<&IpTuple> {
+        match direction {
+            Direction::Original => self.origin.as_ref(),
+            Direction::Reply => self.reply.as_ref(),
+        }
+    }
+
+    /// Returns the counters of the given direction, `counter_origin` or
+    /// `counter_reply`.
+    pub fn counters(&self, direction: Direction) -> Option<&Counter> {
+        match direction {
+            Direction::Original => self.counter_origin.as_ref(),
+            Direction::Reply => self.counter_reply.as_ref(),
+        }
+    }
+
+    /// Returns the TCP sequence number adjustment of the given direction,
+    /// `seq_adj_orig` or `seq_adj_repl`.
+    pub fn seq_adj(&self, direction: Direction) -> Option<&SeqAdj> {
+        match direction {
+            Direction::Original => self.seq_adj_orig.as_ref(),
+            Direction::Reply => self.seq_adj_repl.as_ref(),
+        }
+    }
+
+    /// Returns the transport protocol of the origin tuple, or `None` when the flow
+    /// was decoded without one.
+    pub fn proto(&self) -> Option<L4Proto> {
+        let number = self.origin.as_ref()?.proto.as_ref()?.number?;
+        Some(L4Proto::from(number))
+    }
+
+    /// Returns the protocol specific information of the flow, combining the
+    /// `proto_info` fields with the ICMP fields of the origin tuple. TCP, DCCP and
+    /// SCTP flows decoded without `CTA_PROTOINFO`, e.g. from some events, yield
+    /// their variant with an empty info.
+    pub fn l4_info(&self) -> L4Info {
+        let Some(proto) = self.proto() else {
+            return L4Info::None;
+        };
+        let info = self.proto_info.as_ref();
+        let tuple = self.origin.as_ref().and_then(|o| o.proto.as_ref());
+
+        match proto {
+            L4Proto::Tcp => L4Info::Tcp(info.and_then(|i| i.tcp.clone()).unwrap_or_default()),
+            L4Proto::Dccp => L4Info::Dccp(info.and_then(|i| i.dccp.clone()).unwrap_or_default()),
+            L4Proto::Sctp => L4Info::Sctp(info.and_then(|i| i.sctp.clone()).unwrap_or_default()),
+            L4Proto::Icmp => L4Info::Icmp(IcmpInfo {
+                id: tuple.and_then(|t| t.icmp_id),
+                icmp_type: tuple.and_then(|t| t.icmp_type),
+                code: tuple.and_then(|t| t.icmp_code),
+            }),
+            L4Proto::IcmpV6 => L4Info::Icmp(IcmpInfo {
+                id: tuple.and_then(|t| t.icmpv6_id),
+                icmp_type: tuple.and_then(|t| t.icmpv6_type),
+                code: tuple.and_then(|t| t.icmpv6_code),
+            }),
+            other => L4Info::Other(other),
+        }
+    }
+
+    /// Returns how long the connection has existed, or for a destroyed connection how
+    /// long it lasted. Only available when `net.netfilter.nf_conntrack_timestamp` is
+    /// enabled. The kernel stamps `CTA_TIMESTAMP` with `CLOCK_REALTIME` nanoseconds
+    /// since the epoch, not time since boot, so the age is measured against the wall
+    /// clock, and is skewed when the clock is stepped. Returns `None` if the start
+    /// lies in the future.
+    pub fn age(&self) -> Option<Duration> {
+        let timestamp = self.timestamp.as_ref()?;
+        let end = timestamp.end.unwrap_or_else(Utc::now);
+        (end - timestamp.start?).to_std().ok()
+    }
+
+    /// Flattens the flow into metric labels, for exporting one labeled series per
+    /// flow, e.g. `("proto", "tcp")`, `("src", "10.0.0.1")` and
+    /// `("state", "ESTABLISHED")`. The addresses and ports are those of the origin
+    /// tuple, and the state is the uppercase TCP state, as printed by `conntrack -L`.
+    /// Labels the flow doesn't carry, e.g. the ports of ICMP flows, are left out.
+    /// Unlike the `metrics` module, this needs no feature.
+    pub fn metric_labels(&self) -> Vec<(&'static str, String)> {
+        let origin = self.origin.as_ref();
+        let proto = origin.and_then(|o| o.proto.as_ref());
+        let state = self
+            .proto_info
+            .as_ref()
+            .and_then(|info| info.tcp.as_ref())
+            .and_then(|tcp| tcp.state);
+
+        [
+            ("proto", self.proto().map(|p| p.to_string())),
+            ("src", origin.and_then(|o| o.src).map(|a| a.to_string())),
+            ("dst", origin.and_then(|o| o.dst).map(|a| a.to_string())),
+            (
+                "sport",
+                proto.and_then(|p| p.src_port).map(|p| p.to_string()),
+            ),
+            (
+                "dport",
+                proto.and_then(|p| p.dst_port).map(|p| p.to_string()),
+            ),
+            ("state", state.map(|s| s.to_string().to_ascii_uppercase())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
+
+    /// Returns the counter values of the flow, named like the columns of the CSV
+    /// export. The counters are only present when accounting is enabled.
+    pub fn metric_values(&self) -> Vec<(&'static str, u64)> {
+        let origin = self.counter_origin.as_ref();
+        let reply = self.counter_reply.as_ref();
+
+        [
+            ("orig_packets", origin.and_then(|c| c.packets)),
+            ("orig_bytes", origin.and_then(|c| c.bytes)),
+            ("reply_packets", reply.and_then(|c| c.packets)),
+            ("reply_bytes", reply.and_then(|c| c.bytes)),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
+
+    /// Converts the `Flow` into a `CompleteFlow`, asserting that the fields populated
+    /// for every regular conntrack entry (origin tuple, status, and timeout) are present.
+    /// Returns `Error::IncompleteFlow` naming the first missing field otherwise.
+    pub fn require_complete(self) -> crate::Result<CompleteFlow> {
+        Ok(CompleteFlow {
+            id: self.id,
+            origin: self.origin.ok_or(Error::IncompleteFlow("origin"))?,
+            reply: self.reply,
+            status: self.status.ok_or(Error::IncompleteFlow("status"))?,
+            timeout: self.timeout.ok_or(Error::IncompleteFlow("timeout"))?,
+            proto_info: self.proto_info,
+            counter_origin: self.counter_origin,
+            counter_reply: self.counter_reply,
+            mark: self.mark,
+            zone: self.zone,
+        })
+    }
 }
+
+/// The `CompleteFlow` type is an ergonomic view of a `Flow` for callers who know their
+/// kernel populates the common fields. It is created by `Flow::require_complete()`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CompleteFlow {
+    /// Unique id assigned to this conntrack entry.
+    pub id: Option<u32>,
+    /// The origin of the network traffic.
+    pub origin: IpTuple,
+    /// The reply of the network traffic.
+    pub reply: Option<IpTuple>,
+    /// Contains the status values parsed into the various status flags, represented as strings.
+    pub status: Vec<String>,
+    /// Duration until conntrack entry is invalidated.
+    pub timeout: Duration,
+    /// Metadata specific to the protocol being used to facilitate the network transfer.
+    pub proto_info: Option<ProtoInfo>,
+    /// Byte and packet counter data relative to the traffic origin.
+    pub counter_origin: Option<Counter>,
+    /// Byte and packet counter data relative to the traffic reply.
+    pub counter_reply: Option<Counter>,
+    pub mark: Option<u32>,
+    pub zone: Option<u16>,
+}
+
 #[neli_enum(serialized_type = "u8")]
 #[derive(Serialize, Deserialize)]
 pub enum IpProto {
@@ -85,6 +301,8 @@ pub enum IpProto {
     Esp = 50u8,
     /// Authentication Header protocol  
     Ah = 51u8,
+    /// Internet Control Message Protocol for IPv6
+    Icmpv6 = 58u8,
     /// Multicast Transport Protocol    
     Mtp = 92u8,
     /// IP option pseudo header for BEET  
@@ -109,8 +327,106 @@ pub enum IpProto {
     Raw = 255u8,
 }
 
+impl IpProto {
+    /// Returns `true` for protocols whose conntrack tuples carry source and
+    /// destination ports.
+    pub fn has_ports(&self) -> bool {
+        matches!(
+            self,
+            IpProto::Tcp | IpProto::Udp | IpProto::Udplite | IpProto::Sctp | IpProto::Dccp
+        )
+    }
+}
+
+/// L4Proto names the transport protocols conntrack tracks with their own state, and
+/// keeps the raw protocol number of any other protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum L4Proto {
+    Tcp,
+    Udp,
+    Icmp,
+    IcmpV6,
+    Sctp,
+    Dccp,
+    UdpLite,
+    Gre,
+    Other(u8),
+}
+
+impl From<u8> for L4Proto {
+    fn from(number: u8) -> Self {
+        match IpProto::from(number) {
+            IpProto::Tcp => L4Proto::Tcp,
+            IpProto::Udp => L4Proto::Udp,
+            IpProto::Icmp => L4Proto::Icmp,
+            IpProto::Icmpv6 => L4Proto::IcmpV6,
+            IpProto::Sctp => L4Proto::Sctp,
+            IpProto::Dccp => L4Proto::Dccp,
+            IpProto::Udplite => L4Proto::UdpLite,
+            IpProto::Gre => L4Proto::Gre,
+            _ => L4Proto::Other(number),
+        }
+    }
+}
+
+impl From<L4Proto> for u8 {
+    fn from(proto: L4Proto) -> Self {
+        let proto = match proto {
+            L4Proto::Tcp => IpProto::Tcp,
+            L4Proto::Udp => IpProto::Udp,
+            L4Proto::Icmp => IpProto::Icmp,
+            L4Proto::IcmpV6 => IpProto::Icmpv6,
+            L4Proto::Sctp => IpProto::Sctp,
+            L4Proto::Dccp => IpProto::Dccp,
+            L4Proto::UdpLite => IpProto::Udplite,
+            L4Proto::Gre => IpProto::Gre,
+            L4Proto::Other(number) => return number,
+        };
+        u8::from(proto)
+    }
+}
+
+impl From<IpProto> for L4Proto {
+    fn from(proto: IpProto) -> Self {
+        L4Proto::from(u8::from(proto))
+    }
+}
+
+impl From<L4Proto> for IpProto {
+    fn from(proto: L4Proto) -> Self {
+        IpProto::from(u8::from(proto))
+    }
+}
+
+impl L4Proto {
+    /// Returns the lowercase name of the protocol, as printed by `Display`, without
+    /// allocating. Returns `None` for `Other`, which `Display` prints as a number.
+    pub const fn as_str(&self) -> Option<&'static str> {
+        match self {
+            L4Proto::Tcp => Some("tcp"),
+            L4Proto::Udp => Some("udp"),
+            L4Proto::Icmp => Some("icmp"),
+            L4Proto::IcmpV6 => Some("icmpv6"),
+            L4Proto::Sctp => Some("sctp"),
+            L4Proto::Dccp => Some("dccp"),
+            L4Proto::UdpLite => Some("udplite"),
+            L4Proto::Gre => Some("gre"),
+            L4Proto::Other(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for L4Proto {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            L4Proto::Other(number) => write!(f, "{number}"),
+            proto => f.write_str(proto.as_str().unwrap_or_default()),
+        }
+    }
+}
+
 /// IPTuple contains the source and destination IP as well as protocol information
-#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IpTuple {
     pub src: Option<net::IpAddr>,
     pub dst: Option<net::IpAddr>,
@@ -119,19 +435,73 @@ pub struct IpTuple {
 }
 
 /// ProtoTuple contains information about the used protocol
-#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProtoTuple {
     pub number: Option<IpProto>,
+    /// The ports of port-bearing protocols. The kernel reports the keys of
+    /// `IpProto::Gre` tuples in these fields as well, see `gre_keys()`.
     pub src_port: Option<u16>,
     pub dst_port: Option<u16>,
+    /// ICMP fields, only set for `IpProto::Icmp` tuples.
     pub icmp_id: Option<u16>,
     pub icmp_type: Option<u8>,
     pub icmp_code: Option<u8>,
+    /// ICMPv6 fields, only set for `IpProto::Icmpv6` tuples. The kernel reports these
+    /// with their own attributes, as ICMPv6 type and code values differ from ICMP.
     pub icmpv6_id: Option<u16>,
     pub icmpv6_type: Option<u8>,
     pub icmpv6_code: Option<u8>,
 }
 
+impl IpTuple {
+    /// Uses the tuple as a pattern: returns `true` if every field set in `self`
+    /// equals the corresponding field of `tuple`. Fields left `None` match anything,
+    /// so an empty tuple matches every tuple.
+    pub fn matches(&self, tuple: &IpTuple) -> bool {
+        let proto = match (&self.proto, &tuple.proto) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(pattern), Some(proto)) => pattern.matches(proto),
+        };
+
+        field(&self.src, &tuple.src)
+            && field(&self.dst, &tuple.dst)
+            && field(&self.zone, &tuple.zone)
+            && proto
+    }
+}
+
+impl ProtoTuple {
+    /// Uses the tuple as a pattern like `IpTuple::matches()`.
+    pub fn matches(&self, tuple: &ProtoTuple) -> bool {
+        field(&self.number, &tuple.number)
+            && field(&self.src_port, &tuple.src_port)
+            && field(&self.dst_port, &tuple.dst_port)
+            && field(&self.icmp_id, &tuple.icmp_id)
+            && field(&self.icmp_type, &tuple.icmp_type)
+            && field(&self.icmp_code, &tuple.icmp_code)
+            && field(&self.icmpv6_id, &tuple.icmpv6_id)
+            && field(&self.icmpv6_type, &tuple.icmpv6_type)
+            && field(&self.icmpv6_code, &tuple.icmpv6_code)
+    }
+
+    /// Returns the source and destination keys of an `IpProto::Gre` tuple. These are
+    /// the PPTP call ids for connections handled by the `pptp` helper, and 0 for
+    /// other GRE tunnels. The GRE version isn't reported by the kernel.
+    pub fn gre_keys(&self) -> Option<(u16, u16)> {
+        if self.number != Some(IpProto::Gre) {
+            return None;
+        }
+
+        Some((self.src_port.unwrap_or(0), self.dst_port.unwrap_or(0)))
+    }
+}
+
+/// Matches a pattern field, which matches anything when unset.
+fn field<T: PartialEq>(pattern: &Option<T>, value: &Option<T>) -> bool {
+    pattern.is_none() || pattern == value
+}
+
 /// ProtoInfo contains additional information for certain protocols
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct ProtoInfo {
@@ -155,21 +525,76 @@ pub enum TcpState {
     SynSent2 = 9u8,
 }
 
+impl TcpState {
+    /// Returns the lowercase name used by `conntrack -L`, as printed by `Display`,
+    /// without allocating. Returns `None` for states unknown to this crate, which
+    /// `Display` prints as a number.
+    pub const fn as_str(&self) -> Option<&'static str> {
+        match self {
+            TcpState::None => Some("none"),
+            TcpState::SynSent => Some("syn_sent"),
+            TcpState::SynRecv => Some("syn_recv"),
+            TcpState::Established => Some("established"),
+            TcpState::FinWait => Some("fin_wait"),
+            TcpState::CloseWait => Some("close_wait"),
+            TcpState::LastAck => Some("last_ack"),
+            TcpState::TimeWait => Some("time_wait"),
+            TcpState::Close => Some("close"),
+            TcpState::SynSent2 => Some("syn_sent2"),
+            TcpState::UnrecognizedConst(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for TcpState {
+    /// Formats the state with the lowercase names used by `conntrack -L`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpState::UnrecognizedConst(state) => write!(f, "{state}"),
+            state => f.write_str(state.as_str().unwrap_or_default()),
+        }
+    }
+}
+
 // TCPInfo contains additional information for TCP sessions
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct TcpInfo {
     pub state: Option<TcpState>,
+    /// Window scale factor announced by the origin side.
     pub wscale_orig: Option<u8>,
+    /// Window scale factor announced by the reply side.
     pub wscale_repl: Option<u8>,
+    /// Tracking flags of the origin side, see `TcpFlags`.
     pub flags_orig: Option<TcpFlags>,
+    /// Tracking flags of the reply side, see `TcpFlags`.
     pub flags_reply: Option<TcpFlags>,
 }
 
-// TCPFlags contains additional information for TCP flags
-#[derive(Clone, Default, Debug, Serialize, Deserialize)]
-pub struct TcpFlags {
-    pub flags: Option<u8>,
-    pub mask: Option<u8>,
+impl TcpInfo {
+    /// Returns whether window scaling is in effect for the connection, which
+    /// requires both sides to have announced it. Returns `None` when the flags of
+    /// either side were not reported.
+    pub fn window_scaling(&self) -> Option<bool> {
+        let orig = self.flags_orig?.contains(TcpFlags::WINDOW_SCALE);
+        let reply = self.flags_reply?.contains(TcpFlags::WINDOW_SCALE);
+        Some(orig && reply)
+    }
+}
+
+bitflags! {
+    /// TcpFlags contains the per-direction TCP tracking flags from `CTA_PROTOINFO_TCP`,
+    /// such as whether window scaling or SACK was negotiated by that side.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct TcpFlags: u8 {
+        const WINDOW_SCALE = 0x01;
+        const SACK_PERM = 0x02;
+        const CLOSE_INIT = 0x04;
+        const BE_LIBERAL = 0x08;
+        const DATA_UNACKNOWLEDGED = 0x10;
+        const MAXACK_SET = 0x20;
+        const CHALLENGE_ACK = 0x40;
+        const SIMULTANEOUS_OPEN = 0x80;
+    }
 }
 
 #[neli_enum(serialized_type = "u8")]
@@ -217,6 +642,56 @@ pub struct SctpInfo {
     pub vtag_reply: Option<u32>,
 }
 
+// IcmpInfo contains the fields of an ICMP or ICMPv6 origin tuple
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IcmpInfo {
+    pub id: Option<u16>,
+    pub icmp_type: Option<u8>,
+    pub code: Option<u8>,
+}
+
+/// L4Info holds the protocol specific information of a flow, as returned by
+/// `Flow::l4_info()`, so it can be inspected with a single match on the protocol.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum L4Info {
+    Tcp(TcpInfo),
+    Dccp(DccpInfo),
+    Sctp(SctpInfo),
+    /// The fields of ICMP and ICMPv6 flows, taken from the origin tuple.
+    Icmp(IcmpInfo),
+    /// A protocol without protocol specific information, e.g. `L4Proto::Udp`.
+    Other(L4Proto),
+    /// The flow was decoded without a protocol.
+    None,
+}
+
+/// Direction names one of the two directions of a connection: `Original` is the
+/// traffic sent by the host that initiated it, and `Reply` the traffic sent back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Direction {
+    Original,
+    Reply,
+}
+
+impl Direction {
+    /// Returns the opposite direction.
+    pub const fn reverse(self) -> Self {
+        match self {
+            Direction::Original => Direction::Reply,
+            Direction::Reply => Direction::Original,
+        }
+    }
+
+    /// Returns the attribute type of the tuple of this direction, for encoding a
+    /// `Tuple` with `AttrEncoder::encode()`.
+    pub const fn tuple_attr(self) -> ConntrackAttr {
+        match self {
+            Direction::Original => ConntrackAttr::CtaTupleOrig,
+            Direction::Reply => ConntrackAttr::CtaTupleReply,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Default, Debug, Serialize, Deserialize)]
 pub struct SrcDst(pub Option<net::IpAddr>, pub Option<net::IpAddr>);
 
@@ -227,21 +702,59 @@ pub struct Helper {
     pub info: Option<String>,
 }
 
-// SeqAdj contains additional information about corrections
+// SeqAdj contains the TCP sequence number adjustment of one direction, kept when a
+// helper like the FTP helper changed the payload length of a packet.
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct SeqAdj {
+    /// The sequence number of the last rewritten packet.
     pub correction_pos: Option<u32>,
-    pub offset_before: Option<u32>,
-    pub offset_after: Option<u32>,
+    /// The offset applied to sequence numbers before `correction_pos`. Offsets are
+    /// negative when the helper shortened the payload.
+    pub offset_before: Option<i32>,
+    /// The offset applied to sequence numbers after `correction_pos`.
+    pub offset_after: Option<i32>,
 }
 
-// Counter contains additional information about the traffic
+/// Counter contains additional information about the traffic. The values are
+/// always 64 bit wide: the 64 bit `CTA_COUNTERS_PACKETS` and `CTA_COUNTERS_BYTES`
+/// are preferred over the 32 bit variants sent by ancient kernels, so counters
+/// past 4 GiB aren't truncated.
+///
+/// ```rust
+/// use conntrack::model::*;
+///
+/// // Origin counters of 4000000 packets and 5 GiB, followed by a wrapped 32 bit
+/// // byte count.
+/// let raw = [
+///     0x38, 0x00, 0x00, 0x00, 0x00, 0x01, 0x22, 0x00, 0x00, 0x00, 0x00, 0x00,
+///     0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x24, 0x00, 0x09, 0x80,
+///     0x0c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3d, 0x09, 0x00,
+///     0x0c, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x40, 0x00, 0x00, 0x00,
+///     0x08, 0x00, 0x04, 0x00, 0x40, 0x00, 0x00, 0x00,
+/// ];
+/// let counter = Flow::from_bytes(&raw).unwrap().counter_origin.unwrap();
+///
+/// assert_eq!(counter.packets, Some(4_000_000));
+/// assert_eq!(counter.bytes, Some(5 << 30));
+/// ```
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Counter {
     pub packets: Option<u64>,
     pub bytes: Option<u64>,
 }
 
+// SynProxy contains the state SYNPROXY keeps for a connection it completed the
+// handshake of, in order to translate sequence numbers and timestamps.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct SynProxy {
+    /// The initial sequence number sent by the server.
+    pub isn: Option<u32>,
+    /// The timestamp value SYNPROXY sent to the client.
+    pub its: Option<u32>,
+    /// The offset between the client and server timestamps.
+    pub tsoff: Option<u32>,
+}
+
 // SecCtx contains additional information about the security context
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct SecCtx {
@@ -286,8 +799,655 @@ pub struct Nat {
     pub proto: Option<ProtoTuple>,
 }
 
+/// GlobalStats contains the table-wide statistics returned by
+/// `Conntrack::global_stats()`, equivalent to the `nf_conntrack_count` and
+/// `nf_conntrack_max` sysctls.
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlobalStats {
+    /// The current number of entries in the table.
+    pub entries: Option<u32>,
+    /// The maximum number of entries of the table, not reported by older kernels.
+    pub max_entries: Option<u32>,
+}
+
+impl GlobalStats {
+    /// Returns the fraction of the table in use, between 0 and 1, or `None` when
+    /// either value is missing or the table has no limit.
+    pub fn utilization(&self) -> Option<f64> {
+        let max_entries = self.max_entries.filter(|max| *max > 0)?;
+        Some(f64::from(self.entries?) / f64::from(max_entries))
+    }
+}
+
+/// Tuple is a fully specified connection tuple used to target conntrack entries in
+/// create, get and delete requests. Unlike the decoded `IpTuple`, the protocol and
+/// both addresses are always present.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Tuple {
+    pub proto: IpProto,
+    pub src: net::IpAddr,
+    pub dst: net::IpAddr,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    /// The ICMP fields of `IpProto::Icmp` and `IpProto::Icmpv6` tuples, encoded as
+    /// the ICMP or ICMPv6 attributes according to the protocol.
+    #[serde(default)]
+    pub icmp_id: Option<u16>,
+    #[serde(default)]
+    pub icmp_type: Option<u8>,
+    #[serde(default)]
+    pub icmp_code: Option<u8>,
+}
+
+impl Tuple {
+    /// Checks that both addresses belong to the same family, that ICMP and ICMPv6
+    /// are only used with IPv4 and IPv6 addresses respectively and carry an ICMP id,
+    /// type and code, which the kernel requires, and that ports are only set for
+    /// port-bearing protocols, or as the keys of GRE tuples. Tuples are
+    /// validated again when encoded, so requests with inconsistent tuples fail
+    /// before reaching the kernel.
+    pub fn validate(&self) -> crate::Result<()> {
+        if self.src.is_ipv4() != self.dst.is_ipv4() {
+            return Err(Error::InvalidTuple("src and dst address families differ"));
+        }
+        match self.proto {
+            IpProto::Icmp if self.src.is_ipv6() => {
+                return Err(Error::InvalidTuple("ICMP used with IPv6 addresses"));
+            }
+            IpProto::Icmpv6 if self.src.is_ipv4() => {
+                return Err(Error::InvalidTuple("ICMPv6 used with IPv4 addresses"));
+            }
+            _ => {}
+        }
+        let icmp = [
+            self.icmp_id.is_some(),
+            self.icmp_type.is_some(),
+            self.icmp_code.is_some(),
+        ];
+        match self.proto {
+            IpProto::Icmp | IpProto::Icmpv6 if icmp.contains(&false) => {
+                return Err(Error::InvalidTuple("ICMP tuples need an id, type and code"));
+            }
+            IpProto::Icmp | IpProto::Icmpv6 => {}
+            _ if icmp.contains(&true) => {
+                return Err(Error::InvalidTuple(
+                    "ICMP fields set for a protocol other than ICMP",
+                ));
+            }
+            _ => {}
+        }
+        let keyed = self.proto.has_ports() || self.proto == IpProto::Gre;
+        if !keyed && (self.src_port.is_some() || self.dst_port.is_some()) {
+            return Err(Error::InvalidTuple(
+                "ports set for a protocol without ports",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Builds the origin tuple of a locally initiated connection from the addresses
+    /// of its socket, as returned by `local_addr()` and `peer_addr()`, so it can be
+    /// passed to `Conntrack::get()`. For connections accepted by a listening socket,
+    /// use `reversed()` on the result. IPv4-mapped IPv6 addresses are converted to
+    /// IPv4, as conntrack tracks those connections as IPv4. Ports are dropped for
+    /// protocols that don't carry them. For ICMP and ICMPv6 ping sockets, whose
+    /// local port is the echo identifier, the tuple is that of an echo request.
+    pub fn from_socket_addrs(
+        proto: impl Into<IpProto>,
+        local: net::SocketAddr,
+        peer: net::SocketAddr,
+    ) -> Tuple {
+        let proto = proto.into();
+        let ports = proto.has_ports();
+        let echo_request = match proto {
+            IpProto::Icmp => Some(8),
+            IpProto::Icmpv6 => Some(128),
+            _ => None,
+        };
+
+        Tuple {
+            proto,
+            src: local.ip().to_canonical(),
+            dst: peer.ip().to_canonical(),
+            src_port: ports.then_some(local.port()),
+            dst_port: ports.then_some(peer.port()),
+            icmp_id: echo_request.map(|_| local.port()),
+            icmp_type: echo_request,
+            icmp_code: echo_request.map(|_| 0),
+        }
+    }
+
+    /// Returns the tuple seen from the other side of the connection, with the
+    /// addresses and ports swapped. The ICMP type of a request becomes the type of
+    /// its reply and vice versa, e.g. echo request and echo reply, the way the
+    /// kernel inverts ICMP tuples.
+    pub fn reversed(&self) -> Tuple {
+        Tuple {
+            proto: self.proto,
+            src: self.dst,
+            dst: self.src,
+            src_port: self.dst_port,
+            dst_port: self.src_port,
+            icmp_id: self.icmp_id,
+            icmp_type: self
+                .icmp_type
+                .map(|icmp_type| inverted_icmp_type(self.proto, icmp_type)),
+            icmp_code: self.icmp_code,
+        }
+    }
+}
+
+/// Returns the ICMP type answering `icmp_type`, or answered by it, for the query
+/// types conntrack tracks. Other types are returned unchanged.
+fn inverted_icmp_type(proto: IpProto, icmp_type: u8) -> u8 {
+    const ICMP: [(u8, u8); 4] = [(8, 0), (13, 14), (15, 16), (17, 18)];
+    const ICMPV6: [(u8, u8); 2] = [(128, 129), (139, 140)];
+
+    let pairs: &[(u8, u8)] = match proto {
+        IpProto::Icmp => &ICMP,
+        IpProto::Icmpv6 => &ICMPV6,
+        _ => &[],
+    };
+    pairs
+        .iter()
+        .find_map(|&(request, reply)| match icmp_type {
+            t if t == request => Some(reply),
+            t if t == reply => Some(request),
+            _ => None,
+        })
+        .unwrap_or(icmp_type)
+}
+
+impl From<&Tuple> for IpTuple {
+    fn from(tuple: &Tuple) -> Self {
+        IpTuple {
+            src: Some(tuple.src),
+            dst: Some(tuple.dst),
+            proto: Some(match tuple.proto {
+                IpProto::Icmpv6 => ProtoTuple {
+                    number: Some(tuple.proto),
+                    icmpv6_id: tuple.icmp_id,
+                    icmpv6_type: tuple.icmp_type,
+                    icmpv6_code: tuple.icmp_code,
+                    ..Default::default()
+                },
+                _ => ProtoTuple {
+                    number: Some(tuple.proto),
+                    src_port: tuple.src_port,
+                    dst_port: tuple.dst_port,
+                    icmp_id: tuple.icmp_id,
+                    icmp_type: tuple.icmp_type,
+                    icmp_code: tuple.icmp_code,
+                    ..Default::default()
+                },
+            }),
+            zone: None,
+        }
+    }
+}
+
+/// TupleBuilder assembles a `Tuple` targeting a single connection for the get and
+/// delete requests. The protocol and both addresses are required.
+///
+/// ```rust
+/// use conntrack::model::*;
+///
+/// let tuple = TupleBuilder::default()
+///     .proto(IpProto::Udp)
+///     .src("fd00::1".parse().unwrap())
+///     .dst("fd00::2".parse().unwrap())
+///     .dst_port(53)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(tuple.reversed().src_port, Some(53));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TupleBuilder {
+    proto: Option<IpProto>,
+    src: Option<net::IpAddr>,
+    dst: Option<net::IpAddr>,
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
+    icmp_id: Option<u16>,
+    icmp_type: Option<u8>,
+    icmp_code: Option<u8>,
+}
+
+impl TupleBuilder {
+    pub fn proto(mut self, proto: impl Into<IpProto>) -> Self {
+        self.proto = Some(proto.into());
+        self
+    }
+
+    pub fn src(mut self, src: net::IpAddr) -> Self {
+        self.src = Some(src);
+        self
+    }
+
+    pub fn dst(mut self, dst: net::IpAddr) -> Self {
+        self.dst = Some(dst);
+        self
+    }
+
+    pub fn src_port(mut self, port: u16) -> Self {
+        self.src_port = Some(port);
+        self
+    }
+
+    pub fn dst_port(mut self, port: u16) -> Self {
+        self.dst_port = Some(port);
+        self
+    }
+
+    /// Sets the identifier of an ICMP or ICMPv6 query, e.g. of an echo request.
+    pub fn icmp_id(mut self, id: u16) -> Self {
+        self.icmp_id = Some(id);
+        self
+    }
+
+    pub fn icmp_type(mut self, icmp_type: u8) -> Self {
+        self.icmp_type = Some(icmp_type);
+        self
+    }
+
+    pub fn icmp_code(mut self, code: u8) -> Self {
+        self.icmp_code = Some(code);
+        self
+    }
+
+    /// Validates the configured fields and builds the `Tuple`, see `Tuple::validate()`.
+    pub fn build(self) -> crate::Result<Tuple> {
+        let tuple = Tuple {
+            proto: self.proto.ok_or(Error::IncompleteFlow("proto"))?,
+            src: self.src.ok_or(Error::IncompleteFlow("src"))?,
+            dst: self.dst.ok_or(Error::IncompleteFlow("dst"))?,
+            src_port: self.src_port,
+            dst_port: self.dst_port,
+            icmp_id: self.icmp_id,
+            icmp_type: self.icmp_type,
+            icmp_code: self.icmp_code,
+        };
+        tuple.validate()?;
+
+        Ok(tuple)
+    }
+}
+
+/// NewFlow contains the data encoded by `Conntrack::create()` to inject a conntrack
+/// entry. It is built and validated with a `FlowBuilder`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewFlow {
+    pub origin: Tuple,
+    pub reply: Tuple,
+    pub timeout: Duration,
+    pub zone: Option<u16>,
+    pub mark: Option<u32>,
+    pub status: Option<StatusFlags>,
+    /// The name of the helper to attach, e.g. `"ftp"`.
+    pub helper: Option<String>,
+}
+
+/// FlowBuilder assembles a `NewFlow`. The protocol, origin addresses and timeout are
+/// required; the reply direction defaults to the reversed origin tuple.
+///
+/// ```rust
+/// use conntrack::model::*;
+/// use std::time::Duration;
+///
+/// let flow = FlowBuilder::default()
+///     .proto(IpProto::Tcp)
+///     .src("10.0.0.1".parse().unwrap())
+///     .dst("10.0.0.2".parse().unwrap())
+///     .src_port(40000)
+///     .dst_port(443)
+///     .timeout(Duration::from_secs(120))
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(flow.reply.dst_port, Some(40000));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FlowBuilder {
+    proto: Option<IpProto>,
+    src: Option<net::IpAddr>,
+    dst: Option<net::IpAddr>,
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
+    icmp_id: Option<u16>,
+    icmp_type: Option<u8>,
+    icmp_code: Option<u8>,
+    reply_src: Option<net::IpAddr>,
+    reply_dst: Option<net::IpAddr>,
+    reply_src_port: Option<u16>,
+    reply_dst_port: Option<u16>,
+    zone: Option<u16>,
+    mark: Option<u32>,
+    timeout: Option<Duration>,
+    status: Option<StatusFlags>,
+    helper: Option<String>,
+}
+
+impl FlowBuilder {
+    pub fn proto(mut self, proto: impl Into<IpProto>) -> Self {
+        self.proto = Some(proto.into());
+        self
+    }
+
+    pub fn src(mut self, src: net::IpAddr) -> Self {
+        self.src = Some(src);
+        self
+    }
+
+    pub fn dst(mut self, dst: net::IpAddr) -> Self {
+        self.dst = Some(dst);
+        self
+    }
+
+    pub fn src_port(mut self, port: u16) -> Self {
+        self.src_port = Some(port);
+        self
+    }
+
+    pub fn dst_port(mut self, port: u16) -> Self {
+        self.dst_port = Some(port);
+        self
+    }
+
+    /// Sets the identifier of an ICMP or ICMPv6 query, e.g. of an echo request. The reply
+    /// tuple carries the same identifier, and the type of the reply, see
+    /// `Tuple::reversed()`.
+    pub fn icmp_id(mut self, id: u16) -> Self {
+        self.icmp_id = Some(id);
+        self
+    }
+
+    pub fn icmp_type(mut self, icmp_type: u8) -> Self {
+        self.icmp_type = Some(icmp_type);
+        self
+    }
+
+    pub fn icmp_code(mut self, code: u8) -> Self {
+        self.icmp_code = Some(code);
+        self
+    }
+
+    pub fn reply_src(mut self, src: net::IpAddr) -> Self {
+        self.reply_src = Some(src);
+        self
+    }
+
+    pub fn reply_dst(mut self, dst: net::IpAddr) -> Self {
+        self.reply_dst = Some(dst);
+        self
+    }
+
+    pub fn reply_src_port(mut self, port: u16) -> Self {
+        self.reply_src_port = Some(port);
+        self
+    }
+
+    pub fn reply_dst_port(mut self, port: u16) -> Self {
+        self.reply_dst_port = Some(port);
+        self
+    }
+
+    pub fn zone(mut self, zone: u16) -> Self {
+        self.zone = Some(zone);
+        self
+    }
+
+    pub fn mark(mut self, mark: u32) -> Self {
+        self.mark = Some(mark);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the initial status of the entry, e.g. `StatusFlags::StatusAssured`. Only
+    /// the flags in `StatusFlags::SETTABLE` are accepted.
+    pub fn status(mut self, status: StatusFlags) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Attaches the helper with the given name, e.g. `"ftp"`, so the entry creates
+    /// expectations like one the helper was assigned to by the kernel. The helper
+    /// must be registered for the protocol of the entry, otherwise `create()` fails
+    /// with `EOPNOTSUPP`, or with `EAGAIN` when the kernel just loaded the helper's
+    /// module and the request can be retried.
+    pub fn helper(mut self, name: &str) -> Self {
+        self.helper = Some(name.to_string());
+        self
+    }
+
+    /// Validates the configured fields and builds the `NewFlow`. Returns
+    /// `Error::IncompleteFlow` when a required field is missing,
+    /// `Error::InvalidTuple` when a tuple fails `Tuple::validate()`, e.g. when its
+    /// address families differ or an ICMP tuple lacks its id, type or code, and
+    /// `Error::InvalidStatus`
+    /// when the status contains flags outside of `StatusFlags::SETTABLE`.
+    pub fn build(self) -> crate::Result<NewFlow> {
+        let proto = self.proto.ok_or(Error::IncompleteFlow("proto"))?;
+        // The kernel requires both keys of GRE tuples, which are 0 outside of PPTP.
+        let key = (proto == IpProto::Gre).then_some(0);
+        let origin = Tuple {
+            proto,
+            src: self.src.ok_or(Error::IncompleteFlow("src"))?,
+            dst: self.dst.ok_or(Error::IncompleteFlow("dst"))?,
+            src_port: self.src_port.or(key),
+            dst_port: self.dst_port.or(key),
+            icmp_id: self.icmp_id,
+            icmp_type: self.icmp_type,
+            icmp_code: self.icmp_code,
+        };
+        let reversed = origin.reversed();
+        let reply = Tuple {
+            proto,
+            src: self.reply_src.unwrap_or(reversed.src),
+            dst: self.reply_dst.unwrap_or(reversed.dst),
+            src_port: self.reply_src_port.or(reversed.src_port),
+            dst_port: self.reply_dst_port.or(reversed.dst_port),
+            ..reversed
+        };
+
+        origin.validate()?;
+        reply.validate()?;
+        if let Some(status) = self.status {
+            let unsettable = status.difference(StatusFlags::SETTABLE);
+            if !unsettable.is_empty() {
+                return Err(Error::InvalidStatus(unsettable));
+            }
+        }
+        if origin.src.is_ipv4() != reply.src.is_ipv4() {
+            return Err(Error::InvalidTuple(
+                "origin and reply address families differ",
+            ));
+        }
+
+        Ok(NewFlow {
+            origin,
+            reply,
+            timeout: self.timeout.ok_or(Error::IncompleteFlow("timeout"))?,
+            zone: self.zone,
+            mark: self.mark,
+            status: self.status,
+            helper: self.helper,
+        })
+    }
+}
+
+/// NewExpectation contains the data encoded by `Conntrack::create_expectation()` to
+/// inject an expectation. It is built and validated with an `ExpectationBuilder`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewExpectation {
+    /// The origin tuple of the existing master entry.
+    pub master: Tuple,
+    /// The origin tuple of the expected connection.
+    pub tuple: Tuple,
+    /// The bits of `tuple` an expected connection has to match.
+    pub mask: Tuple,
+    pub timeout: Duration,
+    pub zone: Option<u16>,
+    pub flags: ExpectFlags,
+    /// The name of the helper to assign when the master entry has none.
+    pub helper: Option<String>,
+}
+
+/// ExpectationBuilder assembles a `NewExpectation`. The master tuple, the expected
+/// tuple and the timeout are required. The mask defaults to matching every field
+/// of the expected tuple; a mask with a source port of 0 accepts any source port,
+/// like the data connections expected by the FTP helper.
+///
+/// ```rust
+/// use conntrack::model::*;
+/// use std::time::Duration;
+///
+/// let master = TupleBuilder::default()
+///     .proto(IpProto::Tcp)
+///     .src("10.0.0.1".parse().unwrap())
+///     .dst("10.0.0.2".parse().unwrap())
+///     .src_port(40000)
+///     .dst_port(21)
+///     .build()
+///     .unwrap();
+/// let tuple = TupleBuilder::default()
+///     .proto(IpProto::Tcp)
+///     .src("10.0.0.2".parse().unwrap())
+///     .dst("10.0.0.1".parse().unwrap())
+///     .src_port(0)
+///     .dst_port(50000)
+///     .build()
+///     .unwrap();
+/// let mask = Tuple {
+///     src_port: Some(0),
+///     ..ExpectationBuilder::exact_mask(&tuple)
+/// };
+///
+/// let exp = ExpectationBuilder::default()
+///     .master(master)
+///     .tuple(tuple)
+///     .mask(mask)
+///     .timeout(Duration::from_secs(30))
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(exp.mask.dst_port, Some(0xffff));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ExpectationBuilder {
+    master: Option<Tuple>,
+    tuple: Option<Tuple>,
+    mask: Option<Tuple>,
+    timeout: Option<Duration>,
+    zone: Option<u16>,
+    flags: ExpectFlags,
+    helper: Option<String>,
+}
+
+impl ExpectationBuilder {
+    pub fn master(mut self, master: Tuple) -> Self {
+        self.master = Some(master);
+        self
+    }
+
+    pub fn tuple(mut self, tuple: Tuple) -> Self {
+        self.tuple = Some(tuple);
+        self
+    }
+
+    pub fn mask(mut self, mask: Tuple) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn zone(mut self, zone: u16) -> Self {
+        self.zone = Some(zone);
+        self
+    }
+
+    pub fn flags(mut self, flags: ExpectFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn helper(mut self, helper: impl Into<String>) -> Self {
+        self.helper = Some(helper.into());
+        self
+    }
+
+    /// Returns the mask matching every field of `tuple`.
+    pub fn exact_mask(tuple: &Tuple) -> Tuple {
+        let all = |ip: net::IpAddr| match ip {
+            net::IpAddr::V4(_) => net::IpAddr::V4(net::Ipv4Addr::BROADCAST),
+            net::IpAddr::V6(_) => net::IpAddr::V6(net::Ipv6Addr::from(u128::MAX)),
+        };
+
+        Tuple {
+            proto: tuple.proto,
+            src: all(tuple.src),
+            dst: all(tuple.dst),
+            src_port: tuple.src_port.map(|_| u16::MAX),
+            dst_port: tuple.dst_port.map(|_| u16::MAX),
+            icmp_id: tuple.icmp_id.map(|_| u16::MAX),
+            icmp_type: tuple.icmp_type.map(|_| u8::MAX),
+            icmp_code: tuple.icmp_code.map(|_| u8::MAX),
+        }
+    }
+
+    /// Validates the configured fields and builds the `NewExpectation`.
+    pub fn build(self) -> crate::Result<NewExpectation> {
+        let master = self.master.ok_or(Error::IncompleteFlow("master"))?;
+        let tuple = self.tuple.ok_or(Error::IncompleteFlow("tuple"))?;
+        let mask = self
+            .mask
+            .unwrap_or_else(|| ExpectationBuilder::exact_mask(&tuple));
+
+        master.validate()?;
+        tuple.validate()?;
+        if master.src.is_ipv4() != tuple.src.is_ipv4() || tuple.src.is_ipv4() != mask.src.is_ipv4()
+        {
+            return Err(Error::InvalidTuple(
+                "master, tuple and mask address families differ",
+            ));
+        }
+
+        Ok(NewExpectation {
+            master,
+            tuple,
+            mask,
+            timeout: self.timeout.ok_or(Error::IncompleteFlow("timeout"))?,
+            zone: self.zone,
+            flags: self.flags,
+            helper: self.helper,
+        })
+    }
+}
+
 bitflags! {
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    /// The `NF_CT_EXPECT_*` flags of an expectation.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct ExpectFlags: u32 {
+        /// The expectation stays after a connection matched it.
+        const PERMANENT = 1;
+        /// The expectation doesn't match until it is activated.
+        const INACTIVE = 1 << 1;
+        /// The expectation was created from userspace.
+        const USERSPACE = 1 << 2;
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub struct StatusFlags: u32 {
         const StatusExpected = 1;
         const StatusSeenReply = 1 << 1;
@@ -308,3 +1468,619 @@ bitflags! {
         const StatusOffload = 1 << 14;
     }
 }
+
+impl StatusFlags {
+    /// The status flags that are meaningful when creating an entry. The kernel
+    /// manages the remaining flags itself: NAT bits follow from the NAT attributes,
+    /// and flags like `StatusConfirmed` or `StatusDying` reflect internal state.
+    pub const SETTABLE: StatusFlags = StatusFlags::StatusSeenReply
+        .union(StatusFlags::StatusAssured)
+        .union(StatusFlags::StatusFixedTimeout);
+}
+
+/// DumpFilter holds the conditions the kernel applies to a table dump, so only the
+/// matching flows are transferred. An empty filter dumps the whole table. The
+/// `src_ports()`, `dst_ports()` and `helper()` conditions are the exception, they
+/// are applied by the crate.
+#[derive(Clone, Debug, Default)]
+pub struct DumpFilter {
+    pub(crate) status: Option<(StatusFlags, StatusFlags)>,
+    pub(crate) proto: Option<IpProto>,
+    pub(crate) src: Option<net::IpAddr>,
+    pub(crate) dst: Option<net::IpAddr>,
+    pub(crate) mark: Option<(u32, u32)>,
+    pub(crate) zone: Option<u16>,
+    pub(crate) src_ports: Option<Vec<u16>>,
+    pub(crate) dst_ports: Option<Vec<u16>>,
+    pub(crate) helper: Option<String>,
+}
+
+impl DumpFilter {
+    /// Only dump flows of the given protocol, e.g. `L4Proto::UdpLite` or
+    /// `IpProto::Sctp`. Requires kernel support for `CTA_FILTER` (Linux 5.8).
+    pub fn proto(mut self, proto: impl Into<IpProto>) -> Self {
+        self.proto = Some(proto.into());
+        self
+    }
+
+    /// Only dump flows whose origin tuple has the given source address. Requires
+    /// kernel support for `CTA_FILTER` (Linux 5.8).
+    pub fn src(mut self, src: net::IpAddr) -> Self {
+        self.src = Some(src);
+        self
+    }
+
+    /// Only dump flows whose origin tuple has the given destination address.
+    /// Requires kernel support for `CTA_FILTER` (Linux 5.8).
+    pub fn dst(mut self, dst: net::IpAddr) -> Self {
+        self.dst = Some(dst);
+        self
+    }
+
+    /// Only dump flows whose status flags, restricted to `mask`, equal `flags`. For
+    /// example, `status(StatusAssured, StatusAssured)` dumps only assured flows,
+    /// while `status(StatusFlags::empty(), StatusSeenReply)` dumps unreplied ones.
+    /// An empty `mask` matches every flow. Kernels before Linux 5.19 ignore the
+    /// status filter and dump every flow, see `matches()` for a fallback.
+    pub fn status(mut self, flags: StatusFlags, mask: StatusFlags) -> Self {
+        self.status = (!mask.is_empty()).then_some((flags, mask));
+        self
+    }
+
+    /// Only dump flows whose connection mark, restricted to `mask`, equals `mark`.
+    /// A `mask` of 0 matches every flow.
+    pub fn mark(mut self, mark: u32, mask: u32) -> Self {
+        self.mark = (mask != 0).then_some((mark, mask));
+        self
+    }
+
+    /// Only dump flows in the given conntrack zone. Zone 0 is the default zone of
+    /// flows created without one.
+    pub fn zone(mut self, zone: u16) -> Self {
+        self.zone = Some(zone);
+        self
+    }
+
+    /// Only keep flows whose origin tuple has one of the given source ports. Like
+    /// `dst_ports()`, this is a client side filter.
+    pub fn src_ports(mut self, ports: &[u16]) -> Self {
+        self.src_ports = Some(ports.to_vec());
+        self
+    }
+
+    /// Only keep flows whose origin tuple has one of the given destination ports,
+    /// e.g. `&[80, 443]`. Unlike the other conditions, this is a client side filter,
+    /// as the kernel can't match several ports: every flow is still transferred,
+    /// but the port is read from the raw message, so flows that don't match are
+    /// skipped before being decoded. Flows without ports, e.g. ICMP flows, never
+    /// match.
+    pub fn dst_ports(mut self, ports: &[u16]) -> Self {
+        self.dst_ports = Some(ports.to_vec());
+        self
+    }
+
+    /// Only keep flows with the given helper attached, e.g. `"sip"` or `"ftp"`. Like
+    /// `dst_ports()`, this is a client side filter, as the kernel ignores
+    /// `CTA_HELP` in dump requests: every flow is still transferred, but flows with
+    /// another or no helper are skipped before being decoded.
+    pub fn helper(mut self, name: &str) -> Self {
+        self.helper = Some(name.to_string());
+        self
+    }
+
+    /// Builds a filter from the filter options of the `conntrack` command line tool,
+    /// for porting scripts that shell out to `conntrack -L`. The supported options
+    /// are `-p`/`--proto` with a protocol name or number, `-s`/`--src`, `-d`/`--dst`,
+    /// `--sport`, `--dport`, `-m`/`--mark` with an optional `/MASK`, and
+    /// `-w`/`--zone`, along with their `--orig-*` aliases. Values can also be given
+    /// as `--dport=443`, and numbers in hexadecimal as `0x1f`. Fails with
+    /// `Error::InvalidFilter` on unknown options, missing values and values that
+    /// don't parse.
+    ///
+    /// ```rust
+    /// use conntrack::model::*;
+    ///
+    /// let filter = DumpFilter::from_cli_args(&["-p", "tcp", "--dport", "443"]).unwrap();
+    /// let flow = Flow {
+    ///     origin: Some(IpTuple {
+    ///         proto: Some(ProtoTuple {
+    ///             number: Some(IpProto::Tcp),
+    ///             dst_port: Some(443),
+    ///             ..Default::default()
+    ///         }),
+    ///         ..Default::default()
+    ///     }),
+    ///     ..Default::default()
+    /// };
+    /// assert!(filter.matches(&flow));
+    ///
+    /// assert!(DumpFilter::from_cli_args(&["-m", "0x10/0xf0"]).is_ok());
+    /// assert!(DumpFilter::from_cli_args(&["--reply-src", "10.0.0.1"]).is_err());
+    /// ```
+    pub fn from_cli_args<S: AsRef<str>>(args: &[S]) -> crate::Result<Self> {
+        let mut filter = DumpFilter::default();
+        let mut args = args.iter().map(AsRef::as_ref);
+        while let Some(arg) = args.next() {
+            let (option, value) = match arg.split_once('=') {
+                Some((option, value)) if option.starts_with("--") => (option, Some(value)),
+                _ => (arg, None),
+            };
+            let value = value
+                .or_else(|| args.next())
+                .ok_or_else(|| Error::InvalidFilter(format!("missing value for {option}")))?;
+            let invalid = || Error::InvalidFilter(format!("invalid value for {option}: {value}"));
+
+            filter = match option {
+                "-p" | "--proto" => filter.proto(parse_proto(value).ok_or_else(invalid)?),
+                "-s" | "--src" | "--orig-src" => filter.src(value.parse().map_err(|_| invalid())?),
+                "-d" | "--dst" | "--orig-dst" => filter.dst(value.parse().map_err(|_| invalid())?),
+                "--sport" | "--orig-port-src" => {
+                    filter.src_ports(&[parse_number(value).ok_or_else(invalid)?])
+                }
+                "--dport" | "--orig-port-dst" => {
+                    filter.dst_ports(&[parse_number(value).ok_or_else(invalid)?])
+                }
+                "-m" | "--mark" => {
+                    let (mark, mask) = match value.split_once('/') {
+                        Some((mark, mask)) => (parse_number(mark), parse_number(mask)),
+                        None => (parse_number(value), Some(u32::MAX)),
+                    };
+                    filter.mark(mark.ok_or_else(invalid)?, mask.ok_or_else(invalid)?)
+                }
+                "-w" | "--zone" => filter.zone(parse_number(value).ok_or_else(invalid)?),
+                _ => return Err(Error::InvalidFilter(format!("unsupported option {option}"))),
+            };
+        }
+
+        Ok(filter)
+    }
+
+    /// Applies the filter to an already decoded flow. This is the client side
+    /// fallback for kernels that don't support some of the filter conditions, e.g.
+    /// `conntrack.dump_where(|flow| filter.matches(flow))`.
+    pub fn matches(&self, flow: &Flow) -> bool {
+        if let Some(proto) = self.proto
+            && flow.proto() != Some(L4Proto::from(proto))
+        {
+            return false;
+        }
+        let origin = flow.origin.as_ref();
+        if self.src.is_some() && origin.and_then(|o| o.src) != self.src {
+            return false;
+        }
+        if self.dst.is_some() && origin.and_then(|o| o.dst) != self.dst {
+            return false;
+        }
+        if let Some((mark, mask)) = self.mark
+            && flow.mark.unwrap_or(0) & mask != mark & mask
+        {
+            return false;
+        }
+        if !self.matches_zone(flow.zone) {
+            return false;
+        }
+        let ports = origin.and_then(|o| o.proto.as_ref());
+        if !self.matches_src_port(ports.and_then(|p| p.src_port)) {
+            return false;
+        }
+        if !self.matches_dst_port(ports.and_then(|p| p.dst_port)) {
+            return false;
+        }
+        let helper = flow.helper.as_ref().and_then(|h| h.name.as_deref());
+        if !self.matches_helper(helper.map(|name| name.trim_end_matches('\0'))) {
+            return false;
+        }
+        if let Some((flags, mask)) = self.status {
+            let status = flow
+                .status
+                .iter()
+                .flatten()
+                .filter_map(|name| StatusFlags::from_name(name))
+                .fold(StatusFlags::empty(), |status, flag| status | flag);
+            if status & mask != flags & mask {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Entries of the default zone 0 carry no `CTA_ZONE`.
+    pub(crate) fn matches_zone(&self, zone: Option<u16>) -> bool {
+        self.zone.is_none_or(|filter| zone.unwrap_or(0) == filter)
+    }
+
+    pub(crate) fn matches_src_port(&self, port: Option<u16>) -> bool {
+        matches_port(self.src_ports.as_deref(), port)
+    }
+
+    pub(crate) fn matches_dst_port(&self, port: Option<u16>) -> bool {
+        matches_port(self.dst_ports.as_deref(), port)
+    }
+
+    pub(crate) fn matches_helper(&self, name: Option<&str>) -> bool {
+        self.helper
+            .as_deref()
+            .is_none_or(|helper| name == Some(helper))
+    }
+
+    /// Returns the address families to send dump requests for. The kernel only
+    /// accepts tuple filters within a single address family, so a filter on
+    /// addresses is limited to their family, and a filter on the protocol alone is
+    /// sent for both IPv4 and IPv6.
+    pub(crate) fn families(&self) -> Vec<u8> {
+        match (self.src.or(self.dst), self.proto) {
+            (Some(net::IpAddr::V4(_)), _) => vec![libc::AF_INET as u8],
+            (Some(net::IpAddr::V6(_)), _) => vec![libc::AF_INET6 as u8],
+            (None, Some(_)) => vec![libc::AF_INET as u8, libc::AF_INET6 as u8],
+            (None, None) => vec![libc::AF_UNSPEC as u8],
+        }
+    }
+}
+
+fn matches_port(ports: Option<&[u16]>, port: Option<u16>) -> bool {
+    ports.is_none_or(|ports| port.is_some_and(|port| ports.contains(&port)))
+}
+
+/// Parses a protocol given by name, as printed by `L4Proto`, or by number.
+fn parse_proto(value: &str) -> Option<L4Proto> {
+    if let Ok(number) = value.parse::<u8>() {
+        return Some(L4Proto::from(number));
+    }
+    [
+        L4Proto::Tcp,
+        L4Proto::Udp,
+        L4Proto::Icmp,
+        L4Proto::IcmpV6,
+        L4Proto::Sctp,
+        L4Proto::Dccp,
+        L4Proto::UdpLite,
+        L4Proto::Gre,
+    ]
+    .into_iter()
+    .find(|proto| {
+        proto
+            .as_str()
+            .is_some_and(|name| name.eq_ignore_ascii_case(value))
+    })
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal number, like `strtoul()` does for
+/// the `conntrack` command line tool.
+fn parse_number<T: TryFrom<u64>>(value: &str) -> Option<T> {
+    let number = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => value.parse().ok()?,
+    };
+    T::try_from(number).ok()
+}
+
+bitflags! {
+    /// The conntrack multicast groups an event subscription can join. Each flag maps
+    /// to the corresponding `NFNLGRP_CONNTRACK_*` group, so only the requested event
+    /// types are delivered by the kernel.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct EventGroup: u32 {
+        const NEW = 1 << (libc::NFNLGRP_CONNTRACK_NEW - 1);
+        const UPDATE = 1 << (libc::NFNLGRP_CONNTRACK_UPDATE - 1);
+        const DESTROY = 1 << (libc::NFNLGRP_CONNTRACK_DESTROY - 1);
+    }
+}
+
+/// The `EventType` type tells which change to a conntrack entry an event reports.
+/// It is derived from the netlink message type, and for new entries from the
+/// `NLM_F_CREATE` flag the kernel sets on them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EventType {
+    New,
+    Update,
+    Destroy,
+}
+
+impl EventType {
+    /// Returns the netfilter subsystem the event was sent by. `EventGroup` doesn't
+    /// cover the expectation groups, so this is always `CtNetlink` for now.
+    pub fn subsys(&self) -> CtNetlinkSubsys {
+        CtNetlinkSubsys::CtNetlink
+    }
+}
+
+/// The `Event` type contains a conntrack entry delivered by one of the multicast
+/// groups. For `EventGroup::DESTROY` events, the `Flow` carries the final byte and
+/// packet counters of the connection when accounting is enabled.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Event {
+    /// The multicast group the event was delivered on.
+    #[serde(skip)]
+    pub group: EventGroup,
+    /// The kind of change the event reports.
+    pub event_type: EventType,
+    /// The decoded conntrack entry.
+    pub flow: Flow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_filter(args: &[&str]) -> String {
+        match DumpFilter::from_cli_args(args) {
+            Err(Error::InvalidFilter(message)) => message,
+            other => panic!("expected an invalid filter for {args:?}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn from_cli_args_parses_options() {
+        let filter = DumpFilter::from_cli_args(&[
+            "-p",
+            "udp",
+            "-s",
+            "10.0.0.1",
+            "--dst=2001:db8::2",
+            "--sport",
+            "0x35",
+            "-m",
+            "0x10/0xf0",
+            "-w",
+            "7",
+        ])
+        .unwrap();
+
+        assert_eq!(filter.proto, Some(IpProto::Udp));
+        assert_eq!(filter.src, Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(filter.dst, Some("2001:db8::2".parse().unwrap()));
+        assert_eq!(filter.src_ports, Some(vec![53]));
+        assert_eq!(filter.mark, Some((0x10, 0xf0)));
+        assert_eq!(filter.zone, Some(7));
+    }
+
+    #[test]
+    fn from_cli_args_keeps_the_last_repeated_option() {
+        let filter = DumpFilter::from_cli_args(&[
+            "--dport",
+            "80",
+            "--orig-port-dst",
+            "443",
+            "-s",
+            "10.0.0.1",
+            "-s",
+            "10.0.0.2",
+        ])
+        .unwrap();
+
+        assert_eq!(filter.dst_ports, Some(vec![443]));
+        assert_eq!(filter.src, Some("10.0.0.2".parse().unwrap()));
+    }
+
+    #[test]
+    fn from_cli_args_rejects_unknown_options() {
+        assert_eq!(
+            invalid_filter(&["--reply-src", "10.0.0.1"]),
+            "unsupported option --reply-src"
+        );
+        assert_eq!(invalid_filter(&["-x", "1"]), "unsupported option -x");
+    }
+
+    #[test]
+    fn from_cli_args_rejects_missing_values() {
+        assert_eq!(
+            invalid_filter(&["-p", "tcp", "--dport"]),
+            "missing value for --dport"
+        );
+    }
+
+    #[test]
+    fn from_cli_args_rejects_bad_values() {
+        assert_eq!(
+            invalid_filter(&["-s", "10.0.0.256"]),
+            "invalid value for -s: 10.0.0.256"
+        );
+        assert_eq!(
+            invalid_filter(&["--dst=example.com"]),
+            "invalid value for --dst: example.com"
+        );
+        assert_eq!(
+            invalid_filter(&["--sport", "65536"]),
+            "invalid value for --sport: 65536"
+        );
+        assert_eq!(
+            invalid_filter(&["--dport", "0xzz"]),
+            "invalid value for --dport: 0xzz"
+        );
+        assert_eq!(invalid_filter(&["-p", "foo"]), "invalid value for -p: foo");
+        assert_eq!(invalid_filter(&["-m", "1/x"]), "invalid value for -m: 1/x");
+    }
+
+    /// A flow with the given connlabel bits set in a 128 bit bitmap, laid out in
+    /// native words like the kernel's.
+    fn labeled(bits: &[usize]) -> Flow {
+        let word_bits = usize::BITS as usize;
+        let mut words = vec![0usize; 128 / word_bits];
+        for bit in bits {
+            words[bit / word_bits] |= 1 << (bit % word_bits);
+        }
+
+        Flow {
+            labels: Some(words.iter().flat_map(|word| word.to_ne_bytes()).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn has_label_checks_each_bit() {
+        let flow = labeled(&[0, 63, 64, 127]);
+        for bit in [0, 63, 64, 127] {
+            assert!(flow.has_label(bit), "bit {bit}");
+        }
+        for bit in [1, 62, 65, 126] {
+            assert!(!flow.has_label(bit), "bit {bit}");
+        }
+    }
+
+    fn echo_request() -> Tuple {
+        TupleBuilder::default()
+            .proto(L4Proto::Icmp)
+            .src("192.0.2.1".parse().unwrap())
+            .dst("192.0.2.2".parse().unwrap())
+            .icmp_id(7)
+            .icmp_type(8)
+            .icmp_code(0)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn reversed_echo_request_is_an_echo_reply() {
+        let reply = echo_request().reversed();
+
+        assert_eq!(reply.src, "192.0.2.2".parse::<net::IpAddr>().unwrap());
+        assert_eq!(
+            (reply.icmp_id, reply.icmp_type, reply.icmp_code),
+            (Some(7), Some(0), Some(0))
+        );
+        assert_eq!(reply.reversed(), echo_request());
+    }
+
+    #[test]
+    fn ip_tuple_carries_icmp_fields() {
+        let proto = IpTuple::from(&echo_request()).proto.unwrap();
+        assert_eq!(
+            (proto.icmp_id, proto.icmp_type, proto.icmp_code),
+            (Some(7), Some(8), Some(0))
+        );
+
+        let tuple = Tuple {
+            proto: IpProto::Icmpv6,
+            src: "2001:db8::1".parse().unwrap(),
+            dst: "2001:db8::2".parse().unwrap(),
+            icmp_type: Some(128),
+            ..echo_request()
+        };
+        let proto = IpTuple::from(&tuple).proto.unwrap();
+        assert_eq!(
+            (proto.icmpv6_id, proto.icmpv6_type, proto.icmpv6_code),
+            (Some(7), Some(128), Some(0))
+        );
+        assert_eq!(proto.icmp_id, None);
+    }
+
+    #[test]
+    fn flow_builder_sets_icmp_fields_of_both_directions() {
+        let flow = FlowBuilder::default()
+            .proto(L4Proto::Icmp)
+            .src("192.0.2.1".parse().unwrap())
+            .dst("192.0.2.2".parse().unwrap())
+            .icmp_id(7)
+            .icmp_type(8)
+            .icmp_code(0)
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap();
+
+        assert_eq!(flow.origin, echo_request());
+        assert_eq!(flow.reply, echo_request().reversed());
+    }
+
+    #[test]
+    fn validate_requires_icmp_fields() {
+        let missing = Tuple {
+            icmp_code: None,
+            ..echo_request()
+        };
+        let udp = Tuple {
+            proto: IpProto::Udp,
+            src_port: Some(1),
+            dst_port: Some(53),
+            ..echo_request()
+        };
+
+        assert!(echo_request().validate().is_ok());
+        assert!(matches!(missing.validate(), Err(Error::InvalidTuple(_))));
+        assert!(matches!(udp.validate(), Err(Error::InvalidTuple(_))));
+    }
+
+    #[test]
+    fn from_ping_socket_addrs_is_an_echo_request() {
+        let tuple = Tuple::from_socket_addrs(
+            IpProto::Icmp,
+            "192.0.2.1:7".parse().unwrap(),
+            "192.0.2.2:0".parse().unwrap(),
+        );
+
+        assert_eq!(tuple, echo_request());
+    }
+
+    #[test]
+    fn metric_labels_of_a_tcp_flow() {
+        let flow = Flow {
+            origin: Some(IpTuple {
+                src: Some("10.0.0.1".parse().unwrap()),
+                dst: Some("10.0.0.2".parse().unwrap()),
+                proto: Some(ProtoTuple {
+                    number: Some(IpProto::Tcp),
+                    src_port: Some(40000),
+                    dst_port: Some(443),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            proto_info: Some(ProtoInfo {
+                tcp: Some(TcpInfo {
+                    state: Some(TcpState::Established),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            counter_origin: Some(Counter {
+                packets: Some(3),
+                bytes: Some(180),
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            flow.metric_labels(),
+            [
+                ("proto", "tcp".to_owned()),
+                ("src", "10.0.0.1".to_owned()),
+                ("dst", "10.0.0.2".to_owned()),
+                ("sport", "40000".to_owned()),
+                ("dport", "443".to_owned()),
+                ("state", "ESTABLISHED".to_owned()),
+            ]
+        );
+        assert_eq!(
+            flow.metric_values(),
+            [("orig_packets", 3), ("orig_bytes", 180)]
+        );
+    }
+
+    #[test]
+    fn metric_labels_of_an_icmp_flow_have_no_ports() {
+        let flow = Flow {
+            origin: Some(IpTuple::from(&echo_request())),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            flow.metric_labels(),
+            [
+                ("proto", "icmp".to_owned()),
+                ("src", "192.0.2.1".to_owned()),
+                ("dst", "192.0.2.2".to_owned()),
+            ]
+        );
+        assert!(flow.metric_values().is_empty());
+    }
+
+    #[test]
+    fn has_label_out_of_range() {
+        assert!(!labeled(&[0, 127]).has_label(128));
+        assert!(!labeled(&[0]).has_label(usize::MAX));
+        assert!(!Flow::default().has_label(0));
+    }
+}
@@ -0,0 +1,732 @@
+//! # Table
+//! This module contains an in-memory view of the conntrack table built from
+//! decoded flows, along with aggregations over it.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+use crate::model::*;
+
+/// The `FlowTable` type holds a set of flows keyed by their conntrack id. Flows
+/// without an id cannot be tracked and are ignored on insertion.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct FlowTable {
+    flows: BTreeMap<u32, Flow>,
+}
+
+/// A `Conversation` is a logical connection built from one or more conntrack
+/// entries, carrying the traffic of both directions.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Conversation {
+    /// Ids of the conntrack entries merged into this conversation.
+    pub ids: Vec<u32>,
+    /// The origin tuple of the first entry of the conversation.
+    pub origin: Option<IpTuple>,
+    /// The reply tuple of the first entry of the conversation.
+    pub reply: Option<IpTuple>,
+    /// Traffic sent in the origin direction.
+    pub counter_origin: Counter,
+    /// Traffic sent in the reply direction.
+    pub counter_reply: Counter,
+    /// Packets sent in both directions.
+    pub packets: u64,
+    /// Bytes sent in both directions.
+    pub bytes: u64,
+}
+
+/// A `Summary` counts the entries of a conntrack table per transport protocol and,
+/// for TCP, per TCP state. The counts are kept in ordered maps, so the summary of
+/// the same table always iterates in the same order.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Summary {
+    /// The number of entries.
+    pub total: u64,
+    /// The number of entries per transport protocol.
+    pub protocols: BTreeMap<L4Proto, u64>,
+    /// The number of TCP entries per TCP state.
+    pub tcp_states: BTreeMap<TcpState, u64>,
+}
+
+/// A `FlowDelta` holds the traffic rates of one conntrack entry between two
+/// snapshots, e.g. two dumps taken a few seconds apart.
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FlowDelta {
+    pub id: Option<u32>,
+    pub origin: Option<IpTuple>,
+    /// Bytes per second sent in the origin direction.
+    pub orig_bytes_per_sec: f64,
+    /// Packets per second sent in the origin direction.
+    pub orig_packets_per_sec: f64,
+    /// Bytes per second sent in the reply direction.
+    pub reply_bytes_per_sec: f64,
+    /// Packets per second sent in the reply direction.
+    pub reply_packets_per_sec: f64,
+}
+
+/// The `SortKey` type selects the field `sort_flows()` orders flows by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    /// The bytes of both directions, largest first. Requires accounting, see
+    /// `net.netfilter.nf_conntrack_acct`.
+    Bytes,
+    /// The start timestamp, oldest first. Requires timestamps, see
+    /// `net.netfilter.nf_conntrack_timestamp`.
+    Age,
+    /// The source address of the origin tuple, in ascending order.
+    Src,
+}
+
+type Endpoint = (IpAddr, Option<u16>);
+type ConversationKey = (Option<IpProto>, Endpoint, Endpoint);
+
+impl FlowTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts the flow, returning the previous flow with the same id.
+    pub fn insert(&mut self, flow: Flow) -> Option<Flow> {
+        let id = flow.id?;
+        self.flows.insert(id, flow)
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<Flow> {
+        self.flows.remove(&id)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Flow> {
+        self.flows.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Iterates over the flows in ascending id order.
+    pub fn iter(&self) -> impl Iterator<Item = &Flow> {
+        self.flows.values()
+    }
+
+    /// Returns the master of a related flow, i.e. the flow whose origin tuple is the
+    /// `master` tuple of `flow`, such as the FTP control connection of a data
+    /// connection.
+    pub fn master_of(&self, flow: &Flow) -> Option<&Flow> {
+        let master = flow.master.as_ref().and_then(tuple_key)?;
+        self.flows
+            .values()
+            .find(|f| f.origin.as_ref().and_then(tuple_key) == Some(master))
+    }
+
+    /// Iterates over the flows related to `master`, i.e. the flows whose `master`
+    /// tuple is the origin tuple of `master`.
+    pub fn related_to<'a>(&'a self, master: &Flow) -> impl Iterator<Item = &'a Flow> {
+        let origin = master.origin.as_ref().and_then(tuple_key);
+        self.flows
+            .values()
+            .filter(move |f| origin.is_some() && f.master.as_ref().and_then(tuple_key) == origin)
+    }
+
+    /// Iterates over the flows started more than `age` before `now`, e.g. to find
+    /// leaked connections. The start is the `CTA_TIMESTAMP` start, so flows are
+    /// only found when `net.netfilter.nf_conntrack_timestamp` is enabled; flows
+    /// without a start timestamp are left out.
+    pub fn older_than(&self, age: Duration, now: SystemTime) -> impl Iterator<Item = &Flow> {
+        let now = chrono::DateTime::<chrono::Utc>::from(now);
+        self.flows.values().filter(move |flow| {
+            start(flow)
+                .and_then(|start| (now - start).to_std().ok())
+                .is_some_and(|existed| existed > age)
+        })
+    }
+
+    /// Computes the rates of the flows present in both `self` and the `later`
+    /// snapshot, taken `elapsed` after it, see `FlowDelta::between()`.
+    pub fn deltas(&self, later: &FlowTable, elapsed: Duration) -> Vec<FlowDelta> {
+        self.flows
+            .iter()
+            .filter_map(|(id, flow)| FlowDelta::between(flow, later.get(*id)?, elapsed))
+            .collect()
+    }
+
+    /// Computes the rates of the flows of `self` since the `previous` snapshot,
+    /// taken `elapsed` earlier. Unlike `deltas()`, flows that are new since the
+    /// `previous` snapshot are included, with their whole counters accounted to
+    /// the interval. An id reused by a different entry counts as a new flow.
+    /// Flows that disappeared since the `previous` snapshot are left out.
+    pub fn diff(&self, previous: &FlowTable, elapsed: Duration) -> Vec<FlowDelta> {
+        self.flows
+            .iter()
+            .filter_map(|(id, flow)| {
+                let new = Flow {
+                    id: flow.id,
+                    origin: flow.origin.clone(),
+                    ..Default::default()
+                };
+                let before = previous
+                    .get(*id)
+                    .filter(|before| before.origin == flow.origin)
+                    .unwrap_or(&new);
+                FlowDelta::between(before, flow, elapsed)
+            })
+            .collect()
+    }
+
+    /// Counts the flows per protocol and TCP state.
+    pub fn summary(&self) -> Summary {
+        self.flows.values().collect()
+    }
+
+    /// Groups the flows into conversations. Every flow yields both of its directions,
+    /// and flows whose origin tuple mirrors the origin tuple of another flow are
+    /// merged into the same conversation, with their counters swapped accordingly.
+    pub fn conversations(&self) -> Vec<Conversation> {
+        let mut keyed: BTreeMap<ConversationKey, Conversation> = BTreeMap::new();
+        let mut unkeyed = Vec::new();
+
+        for flow in self.flows.values() {
+            let Some((key, mirrored)) = flow.origin.as_ref().and_then(conversation_key) else {
+                let mut conversation = Conversation::default();
+                conversation.add(flow, false);
+                unkeyed.push(conversation);
+                continue;
+            };
+
+            match keyed.entry(key) {
+                Entry::Occupied(mut entry) => {
+                    let first_mirrored = entry
+                        .get()
+                        .origin
+                        .as_ref()
+                        .and_then(conversation_key)
+                        .is_some_and(|(_, mirrored)| mirrored);
+                    entry.get_mut().add(flow, mirrored != first_mirrored);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(Conversation::default()).add(flow, false);
+                }
+            }
+        }
+
+        keyed.into_values().chain(unkeyed).collect()
+    }
+}
+
+impl FlowDelta {
+    /// Computes the rates of the entry seen as `a` and, `elapsed` later, as `b`.
+    /// Returns `None` if the two flows aren't the same entry, i.e. their ids or
+    /// origin tuples differ, or if no time elapsed. A counter that went down, e.g.
+    /// after `Conntrack::reset_all_counters()`, yields a rate of 0, and so do
+    /// missing counters.
+    pub fn between(a: &Flow, b: &Flow, elapsed: Duration) -> Option<FlowDelta> {
+        if a.id != b.id || a.origin != b.origin || elapsed.is_zero() {
+            return None;
+        }
+
+        let secs = elapsed.as_secs_f64();
+        let rate =
+            |a: &Option<Counter>, b: &Option<Counter>, field: fn(&Counter) -> Option<u64>| {
+                let a = a.as_ref().and_then(field).unwrap_or(0);
+                let b = b.as_ref().and_then(field).unwrap_or(0);
+                b.saturating_sub(a) as f64 / secs
+            };
+
+        Some(FlowDelta {
+            id: b.id,
+            origin: b.origin.clone(),
+            orig_bytes_per_sec: rate(&a.counter_origin, &b.counter_origin, |c| c.bytes),
+            orig_packets_per_sec: rate(&a.counter_origin, &b.counter_origin, |c| c.packets),
+            reply_bytes_per_sec: rate(&a.counter_reply, &b.counter_reply, |c| c.bytes),
+            reply_packets_per_sec: rate(&a.counter_reply, &b.counter_reply, |c| c.packets),
+        })
+    }
+}
+
+impl Conversation {
+    fn add(&mut self, flow: &Flow, swapped: bool) {
+        if let Some(id) = flow.id {
+            self.ids.push(id);
+        }
+        if self.origin.is_none() && self.reply.is_none() {
+            self.origin = flow.origin.clone();
+            self.reply = flow.reply.clone();
+        }
+
+        let origin = if swapped {
+            Direction::Reply
+        } else {
+            Direction::Original
+        };
+        for (total, counter) in [
+            (&mut self.counter_origin, flow.counters(origin)),
+            (&mut self.counter_reply, flow.counters(origin.reverse())),
+        ] {
+            let packets = counter.and_then(|c| c.packets).unwrap_or(0);
+            let bytes = counter.and_then(|c| c.bytes).unwrap_or(0);
+            total.packets = Some(total.packets.unwrap_or(0) + packets);
+            total.bytes = Some(total.bytes.unwrap_or(0) + bytes);
+            self.packets += packets;
+            self.bytes += bytes;
+        }
+    }
+}
+
+impl Summary {
+    /// Counts the flow in the summary.
+    pub fn record(&mut self, flow: &Flow) {
+        let state = flow
+            .proto_info
+            .as_ref()
+            .and_then(|info| info.tcp.as_ref())
+            .and_then(|tcp| tcp.state);
+        self.add(flow.proto(), state);
+    }
+
+    pub(crate) fn add(&mut self, proto: Option<L4Proto>, tcp_state: Option<TcpState>) {
+        self.total += 1;
+        if let Some(proto) = proto {
+            *self.protocols.entry(proto).or_default() += 1;
+        }
+        if let Some(state) = tcp_state {
+            *self.tcp_states.entry(state).or_default() += 1;
+        }
+    }
+}
+
+impl<'a> FromIterator<&'a Flow> for Summary {
+    fn from_iter<I: IntoIterator<Item = &'a Flow>>(iter: I) -> Self {
+        let mut summary = Summary::default();
+        for flow in iter {
+            summary.record(flow);
+        }
+        summary
+    }
+}
+
+impl FromIterator<Flow> for FlowTable {
+    fn from_iter<I: IntoIterator<Item = Flow>>(iter: I) -> Self {
+        let mut table = FlowTable::new();
+        for flow in iter {
+            table.insert(flow);
+        }
+        table
+    }
+}
+
+/// Renders the flows as a column aligned table like `conntrack -L`, with a header
+/// line followed by one line per flow. Each column is as wide as its widest cell,
+/// so long IPv6 addresses widen their column instead of shifting the others.
+/// Cells the flow doesn't carry, e.g. the TCP state of UDP flows, show as `-`.
+pub fn format_table(flows: &[Flow]) -> String {
+    const HEADER: [&str; 6] = ["proto", "state", "orig", "reply", "bytes", "mark"];
+
+    let rows: Vec<[String; 6]> = flows
+        .iter()
+        .map(|flow| {
+            let state = flow
+                .proto_info
+                .as_ref()
+                .and_then(|info| info.tcp.as_ref())
+                .and_then(|tcp| tcp.state);
+            [
+                cell(flow.proto()),
+                cell(state),
+                flow.origin
+                    .as_ref()
+                    .map_or_else(|| "-".to_string(), endpoints),
+                flow.reply
+                    .as_ref()
+                    .map_or_else(|| "-".to_string(), endpoints),
+                cell(total_bytes(flow)),
+                cell(flow.mark),
+            ]
+        })
+        .collect();
+
+    let mut widths = HEADER.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    let header = HEADER.map(str::to_string);
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Sorts the flows by the field selected with `by`. Flows missing the field, e.g.
+/// without counters when sorting by bytes, come last. Ties are broken by the origin
+/// tuple and then the id, so the same flows are always sorted the same way.
+pub fn sort_flows(flows: &mut [Flow], by: SortKey) {
+    flows.sort_by(|a, b| {
+        let order = match by {
+            SortKey::Bytes => {
+                missing_last(total_bytes(a).map(Reverse), total_bytes(b).map(Reverse))
+            }
+            SortKey::Age => missing_last(start(a), start(b)),
+            SortKey::Src => missing_last(
+                a.origin.as_ref().and_then(|o| o.src),
+                b.origin.as_ref().and_then(|o| o.src),
+            ),
+        };
+
+        order
+            .then_with(|| {
+                missing_last(
+                    a.origin.as_ref().and_then(tuple_key),
+                    b.origin.as_ref().and_then(tuple_key),
+                )
+            })
+            .then_with(|| missing_last(a.id, b.id))
+    });
+}
+
+fn missing_last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    }
+}
+
+fn total_bytes(flow: &Flow) -> Option<u64> {
+    [&flow.counter_origin, &flow.counter_reply]
+        .into_iter()
+        .filter_map(|counter| counter.as_ref().and_then(|c| c.bytes))
+        .reduce(|total, bytes| total + bytes)
+}
+
+fn start(flow: &Flow) -> Option<chrono::DateTime<chrono::Utc>> {
+    flow.timestamp.as_ref().and_then(|t| t.start)
+}
+
+fn cell(value: Option<impl std::fmt::Display>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+/// Formats the endpoints of a tuple as `src:sport -> dst:dport`, with IPv6
+/// addresses in brackets.
+fn endpoints(tuple: &IpTuple) -> String {
+    let proto = tuple.proto.as_ref();
+    let endpoint = |ip: Option<IpAddr>, port: Option<u16>| {
+        let ip = match ip {
+            Some(IpAddr::V6(ip)) if port.is_some() => format!("[{ip}]"),
+            Some(ip) => ip.to_string(),
+            None => "?".to_string(),
+        };
+        match port {
+            Some(port) => format!("{ip}:{port}"),
+            None => ip,
+        }
+    };
+
+    format!(
+        "{} -> {}",
+        endpoint(tuple.src, proto.and_then(|p| p.src_port)),
+        endpoint(tuple.dst, proto.and_then(|p| p.dst_port))
+    )
+}
+
+fn tuple_key(tuple: &IpTuple) -> Option<ConversationKey> {
+    let proto = tuple.proto.as_ref();
+    let src = (tuple.src?, proto.and_then(|p| p.src_port));
+    let dst = (tuple.dst?, proto.and_then(|p| p.dst_port));
+
+    Some((proto.and_then(|p| p.number), src, dst))
+}
+
+/// Builds a direction independent key from the endpoints of a tuple, returning
+/// whether the endpoints had to be swapped to produce it.
+fn conversation_key(tuple: &IpTuple) -> Option<(ConversationKey, bool)> {
+    let (number, src, dst) = tuple_key(tuple)?;
+
+    if src <= dst {
+        Some(((number, src, dst), false))
+    } else {
+        Some(((number, dst, src), true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuple(src: &str, src_port: u16, dst: &str, dst_port: u16) -> IpTuple {
+        IpTuple {
+            src: Some(src.parse().unwrap()),
+            dst: Some(dst.parse().unwrap()),
+            proto: Some(ProtoTuple {
+                number: Some(IpProto::Tcp),
+                src_port: Some(src_port),
+                dst_port: Some(dst_port),
+                ..Default::default()
+            }),
+            zone: None,
+        }
+    }
+
+    fn counter(packets: u64, bytes: u64) -> Option<Counter> {
+        Some(Counter {
+            packets: Some(packets),
+            bytes: Some(bytes),
+        })
+    }
+
+    /// A TCP flow from `src_port` on 10.0.0.1 to port 80 on 10.0.0.2, with `orig`
+    /// and `reply` bytes sent in its two directions.
+    fn flow(id: u32, src_port: u16, orig: u64, reply: u64) -> Flow {
+        Flow {
+            id: Some(id),
+            origin: Some(tuple("10.0.0.1", src_port, "10.0.0.2", 80)),
+            reply: Some(tuple("10.0.0.2", 80, "10.0.0.1", src_port)),
+            counter_origin: counter(1, orig),
+            counter_reply: counter(1, reply),
+            ..Default::default()
+        }
+    }
+
+    fn with_state(mut flow: Flow, state: TcpState) -> Flow {
+        flow.proto_info = Some(ProtoInfo {
+            tcp: Some(TcpInfo {
+                state: Some(state),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        flow
+    }
+
+    fn started(mut flow: Flow, start: SystemTime) -> Flow {
+        flow.timestamp = Some(Timestamp {
+            start: Some(start.into()),
+            end: None,
+        });
+        flow
+    }
+
+    fn ids(flows: &[Flow]) -> Vec<u32> {
+        flows.iter().filter_map(|flow| flow.id).collect()
+    }
+
+    #[test]
+    fn flow_table_keys_flows_by_id() {
+        let mut table = FlowTable::new();
+        assert!(table.insert(flow(1, 1000, 0, 0)).is_none());
+        assert!(table.insert(flow(1, 1001, 0, 0)).is_some());
+        assert!(
+            table
+                .insert(Flow {
+                    id: None,
+                    ..flow(2, 1002, 0, 0)
+                })
+                .is_none()
+        );
+
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.get(1).unwrap().origin, flow(1, 1001, 0, 0).origin);
+        assert!(table.remove(1).is_some());
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn conversations_pair_mirrored_flows() {
+        let forward = flow(1, 1000, 100, 200);
+        let mirrored = Flow {
+            id: Some(2),
+            origin: forward.reply.clone(),
+            reply: forward.origin.clone(),
+            counter_origin: counter(1, 50),
+            counter_reply: counter(1, 25),
+            ..Default::default()
+        };
+        let table: FlowTable = [forward.clone(), mirrored, flow(3, 1001, 10, 20)]
+            .into_iter()
+            .collect();
+
+        let conversations = table.conversations();
+        assert_eq!(conversations.len(), 2);
+
+        let paired = &conversations[0];
+        assert_eq!(paired.ids, vec![1, 2]);
+        assert_eq!(paired.origin, forward.origin);
+        assert_eq!(paired.counter_origin.bytes, Some(125));
+        assert_eq!(paired.counter_reply.bytes, Some(250));
+        assert_eq!(paired.packets, 4);
+        assert_eq!(paired.bytes, 375);
+
+        assert_eq!(conversations[1].ids, vec![3]);
+        assert_eq!(conversations[1].bytes, 30);
+    }
+
+    #[test]
+    fn summary_counts_protocols_and_states() {
+        let udp = Flow {
+            id: Some(3),
+            origin: Some(IpTuple {
+                proto: Some(ProtoTuple {
+                    number: Some(IpProto::Udp),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let table: FlowTable = [
+            with_state(flow(1, 1000, 0, 0), TcpState::Established),
+            with_state(flow(2, 1001, 0, 0), TcpState::TimeWait),
+            udp,
+        ]
+        .into_iter()
+        .collect();
+
+        let summary = table.summary();
+        assert_eq!(summary.total, 3);
+        assert_eq!(summary.protocols[&L4Proto::Tcp], 2);
+        assert_eq!(summary.protocols[&L4Proto::Udp], 1);
+        assert_eq!(summary.tcp_states[&TcpState::Established], 1);
+        assert_eq!(summary.tcp_states[&TcpState::TimeWait], 1);
+        assert_eq!(summary, table.iter().collect());
+    }
+
+    #[test]
+    fn delta_between_two_snapshots() {
+        let delta = FlowDelta::between(
+            &flow(1, 1000, 100, 50),
+            &flow(1, 1000, 300, 40),
+            Duration::from_secs(2),
+        )
+        .unwrap();
+        assert_eq!(delta.id, Some(1));
+        assert_eq!(delta.orig_bytes_per_sec, 100.0);
+        assert_eq!(delta.orig_packets_per_sec, 0.0);
+        // The reply counter went down, e.g. after a counter reset.
+        assert_eq!(delta.reply_bytes_per_sec, 0.0);
+
+        let elapsed = Duration::from_secs(1);
+        assert!(FlowDelta::between(&flow(1, 1000, 0, 0), &flow(2, 1000, 0, 0), elapsed).is_none());
+        assert!(FlowDelta::between(&flow(1, 1000, 0, 0), &flow(1, 1001, 0, 0), elapsed).is_none());
+        assert!(
+            FlowDelta::between(&flow(1, 1000, 0, 0), &flow(1, 1000, 0, 0), Duration::ZERO)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn deltas_and_diff_of_snapshots() {
+        let previous: FlowTable = [
+            flow(1, 1000, 100, 0),
+            flow(2, 1001, 0, 0),
+            flow(3, 1002, 0, 0),
+        ]
+        .into_iter()
+        .collect();
+        // Flow 2 is gone, id 3 was reused by a different entry and flow 4 is new.
+        let current: FlowTable = [
+            flow(1, 1000, 400, 0),
+            flow(3, 2000, 30, 0),
+            flow(4, 1003, 60, 0),
+        ]
+        .into_iter()
+        .collect();
+        let elapsed = Duration::from_secs(3);
+
+        let deltas = previous.deltas(&current, elapsed);
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].id, Some(1));
+        assert_eq!(deltas[0].orig_bytes_per_sec, 100.0);
+
+        let diff = current.diff(&previous, elapsed);
+        let rates: Vec<_> = diff
+            .iter()
+            .map(|delta| (delta.id.unwrap(), delta.orig_bytes_per_sec))
+            .collect();
+        assert_eq!(rates, vec![(1, 100.0), (3, 10.0), (4, 20.0)]);
+    }
+
+    #[test]
+    fn older_than_needs_a_start() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let table: FlowTable = [
+            started(flow(1, 1000, 0, 0), now - Duration::from_secs(600)),
+            started(flow(2, 1001, 0, 0), now - Duration::from_secs(10)),
+            flow(3, 1002, 0, 0),
+        ]
+        .into_iter()
+        .collect();
+
+        let old: Vec<_> = table
+            .older_than(Duration::from_secs(60), now)
+            .filter_map(|flow| flow.id)
+            .collect();
+        assert_eq!(old, vec![1]);
+    }
+
+    #[test]
+    fn sort_flows_puts_missing_fields_last() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let unknown = Flow {
+            id: Some(9),
+            ..Default::default()
+        };
+        let mut flows = vec![
+            unknown,
+            started(flow(1, 1000, 10, 0), now),
+            started(flow(2, 1001, 300, 0), now - Duration::from_secs(5)),
+            flow(3, 1002, 20, 0),
+        ];
+
+        sort_flows(&mut flows, SortKey::Bytes);
+        assert_eq!(ids(&flows), vec![2, 3, 1, 9]);
+
+        sort_flows(&mut flows, SortKey::Age);
+        assert_eq!(ids(&flows), vec![2, 1, 3, 9]);
+
+        // Equal sources are ordered by their tuple.
+        sort_flows(&mut flows, SortKey::Src);
+        assert_eq!(ids(&flows), vec![1, 2, 3, 9]);
+    }
+
+    #[test]
+    fn format_table_aligns_columns() {
+        let mut marked = with_state(flow(1, 1000, 100, 200), TcpState::Established);
+        marked.mark = Some(7);
+        let v6 = Flow {
+            origin: Some(IpTuple {
+                src: Some("2001:db8::1".parse().unwrap()),
+                dst: Some("2001:db8::2".parse().unwrap()),
+                proto: Some(ProtoTuple {
+                    number: Some(IpProto::Icmpv6),
+                    ..Default::default()
+                }),
+                zone: None,
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            format_table(&[marked, v6]),
+            "\
+proto   state        orig                          reply                         bytes  mark
+tcp     established  10.0.0.1:1000 -> 10.0.0.2:80  10.0.0.2:80 -> 10.0.0.1:1000  300    7
+icmpv6  -            2001:db8::1 -> 2001:db8::2    -                             -      -
+"
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! # View
+//! This module contains borrowed views over raw conntrack messages, which read
+//! single fields on demand instead of decoding and allocating a whole `Flow`.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::attributes::*;
+use crate::model::*;
+use crate::result::*;
+
+const NLMSG_HDRLEN: usize = 16;
+const NFGENMSG_LEN: usize = 4;
+const NLA_HDRLEN: usize = 4;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+/// The `FlowHeader` type holds the few fields of a conntrack entry needed to
+/// classify it, as yielded by `Conntrack::dump_headers()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlowHeader {
+    pub id: Option<u32>,
+    pub proto: Option<L4Proto>,
+    pub status: Option<StatusFlags>,
+    pub tcp_state: Option<TcpState>,
+}
+
+/// The `FlowRef` type is a read-only view of a single conntrack entry, borrowing
+/// the raw netlink message it was received in. Fields are located by walking the
+/// attributes each time an accessor is called, so a `FlowRef` is cheap to create
+/// and discard while scanning large tables. Use `to_owned()` to decode the whole
+/// entry into a `Flow`.
+#[derive(Clone, Copy, Debug)]
+pub struct FlowRef<'a> {
+    raw: &'a [u8],
+}
+
+impl<'a> FlowRef<'a> {
+    /// Creates a view of the raw netlink message, i.e. the netlink header followed
+    /// by the netfilter header and the conntrack attributes. Only the message
+    /// length is validated, attributes are read lazily.
+    pub fn from_bytes(raw: &'a [u8]) -> Result<FlowRef<'a>> {
+        let len = raw
+            .get(..4)
+            .map(|len| u32::from_ne_bytes([len[0], len[1], len[2], len[3]]) as usize)
+            .ok_or_else(|| Error::Netlink("truncated netlink header".to_string()))?;
+        if len < NLMSG_HDRLEN + NFGENMSG_LEN || len > raw.len() {
+            return Err(Error::Netlink(format!(
+                "invalid netlink message length: {len}"
+            )));
+        }
+
+        Ok(FlowRef { raw: &raw[..len] })
+    }
+
+    /// Returns the address family from the netfilter header, `libc::AF_INET` or
+    /// `libc::AF_INET6`.
+    pub fn family(&self) -> u8 {
+        self.raw[NLMSG_HDRLEN]
+    }
+
+    pub fn id(&self) -> Option<u32> {
+        self.attr(ConntrackAttr::CtaId).and_then(be_u32)
+    }
+
+    /// Returns the transport protocol of the origin tuple.
+    pub fn proto(&self) -> Option<L4Proto> {
+        let tuple = self.attr(ConntrackAttr::CtaTupleOrig)?;
+        let proto = find(tuple, TupleAttr::CtaTupleProto)?;
+        let number = find(proto, ProtoTupleAttr::CtaProtoNum)?;
+
+        number.first().map(|&number| L4Proto::from(number))
+    }
+
+    /// Returns the source port of the origin tuple.
+    pub fn src_port(&self) -> Option<u16> {
+        let tuple = self.attr(ConntrackAttr::CtaTupleOrig)?;
+        let proto = find(tuple, TupleAttr::CtaTupleProto)?;
+
+        find(proto, ProtoTupleAttr::CtaProtoSrcPort).and_then(be_u16)
+    }
+
+    /// Returns the destination port of the origin tuple.
+    pub fn dst_port(&self) -> Option<u16> {
+        let tuple = self.attr(ConntrackAttr::CtaTupleOrig)?;
+        let proto = find(tuple, TupleAttr::CtaTupleProto)?;
+
+        find(proto, ProtoTupleAttr::CtaProtoDstPort).and_then(be_u16)
+    }
+
+    pub fn orig_tuple(&self) -> Option<IpTuple> {
+        self.attr(ConntrackAttr::CtaTupleOrig).map(tuple)
+    }
+
+    pub fn reply_tuple(&self) -> Option<IpTuple> {
+        self.attr(ConntrackAttr::CtaTupleReply).map(tuple)
+    }
+
+    pub fn status(&self) -> Option<StatusFlags> {
+        let status = self.attr(ConntrackAttr::CtaStatus).and_then(be_u32)?;
+        Some(StatusFlags::from_bits_retain(status))
+    }
+
+    pub fn mark(&self) -> Option<u32> {
+        self.attr(ConntrackAttr::CtaMark).and_then(be_u32)
+    }
+
+    /// Returns the numeric security mark, which is distinct from `mark()`.
+    pub fn sec_mark(&self) -> Option<u32> {
+        self.attr(ConntrackAttr::CtaSecMark).and_then(be_u32)
+    }
+
+    /// Returns the name of the helper attached to the entry, e.g. `"ftp"`.
+    pub fn helper_name(&self) -> Option<&'a str> {
+        let helper = self.attr(ConntrackAttr::CtaHelp)?;
+        let name = find(helper, HelperAttr::CtaHelpName)?;
+
+        std::str::from_utf8(name)
+            .ok()
+            .map(|name| name.trim_end_matches('\0'))
+    }
+
+    pub fn zone(&self) -> Option<u16> {
+        self.attr(ConntrackAttr::CtaZone).and_then(be_u16)
+    }
+
+    pub fn tcp_state(&self) -> Option<TcpState> {
+        let info = self.attr(ConntrackAttr::CtaProtoInfo)?;
+        let tcp = find(info, ProtoInfoAttr::CtaProtoInfoTcp)?;
+        let state = find(tcp, TcpInfoAttr::CtaProtoInfoTcpState)?;
+
+        state.first().map(|&state| TcpState::from(state))
+    }
+
+    /// Reads the fields of a `FlowHeader`.
+    pub fn header(&self) -> FlowHeader {
+        FlowHeader {
+            id: self.id(),
+            proto: self.proto(),
+            status: self.status(),
+            tcp_state: self.tcp_state(),
+        }
+    }
+
+    /// Decodes the whole entry into an owned `Flow`.
+    pub fn to_owned(&self) -> Result<Flow> {
+        Flow::from_bytes(self.raw)
+    }
+
+    /// Returns the raw netlink message the view borrows.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    pub(crate) fn nl_seq(&self) -> u32 {
+        u32::from_ne_bytes([self.raw[8], self.raw[9], self.raw[10], self.raw[11]])
+    }
+
+    pub(crate) fn nl_flags(&self) -> i32 {
+        i32::from(u16::from_ne_bytes([self.raw[6], self.raw[7]]))
+    }
+
+    pub(crate) fn nl_type(&self) -> i32 {
+        i32::from(u16::from_ne_bytes([self.raw[4], self.raw[5]]))
+    }
+
+    /// Returns the errno of an `NLMSG_ERROR` message, which is negative, or 0 for
+    /// an acknowledgement.
+    pub(crate) fn nl_error(&self) -> i32 {
+        let error = &self.raw[NLMSG_HDRLEN..NLMSG_HDRLEN + 4];
+        i32::from_ne_bytes([error[0], error[1], error[2], error[3]])
+    }
+
+    fn attr(&self, attr_type: ConntrackAttr) -> Option<&'a [u8]> {
+        find(&self.raw[NLMSG_HDRLEN + NFGENMSG_LEN..], attr_type)
+    }
+}
+
+/// Iterates over the type and payload of each attribute in `buf`, stopping at the
+/// first malformed attribute.
+fn attrs(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let len = u16::from_ne_bytes([*buf.first()?, *buf.get(1)?]) as usize;
+        let attr_type = u16::from_ne_bytes([*buf.get(2)?, *buf.get(3)?]) & NLA_TYPE_MASK;
+        let payload = buf.get(NLA_HDRLEN..len)?;
+
+        buf = buf.get(len.next_multiple_of(4)..).unwrap_or_default();
+        Some((attr_type, payload))
+    })
+}
+
+/// Returns the payload of the attribute of the given type. Like the decoders, the
+/// last instance wins if the attribute is repeated.
+fn find<T: Into<u16>>(buf: &[u8], attr_type: T) -> Option<&[u8]> {
+    let attr_type = attr_type.into();
+    attrs(buf)
+        .filter_map(|(t, payload)| (t == attr_type).then_some(payload))
+        .last()
+}
+
+fn tuple(buf: &[u8]) -> IpTuple {
+    let mut tuple = IpTuple::default();
+
+    for (attr_type, payload) in attrs(buf) {
+        match TupleAttr::from(attr_type) {
+            TupleAttr::CtaTupleIp => {
+                for (attr_type, payload) in attrs(payload) {
+                    match IpTupleAttr::from(attr_type) {
+                        IpTupleAttr::CtaIpv4Src => tuple.src = ipv4(payload),
+                        IpTupleAttr::CtaIpv4Dst => tuple.dst = ipv4(payload),
+                        IpTupleAttr::CtaIpv6Src => tuple.src = ipv6(payload),
+                        IpTupleAttr::CtaIpv6Dst => tuple.dst = ipv6(payload),
+                        _ => {}
+                    }
+                }
+            }
+            TupleAttr::CtaTupleProto => {
+                let mut proto = ProtoTuple::default();
+                for (attr_type, payload) in attrs(payload) {
+                    match ProtoTupleAttr::from(attr_type) {
+                        ProtoTupleAttr::CtaProtoNum => {
+                            proto.number = payload.first().map(|&n| IpProto::from(n));
+                        }
+                        ProtoTupleAttr::CtaProtoSrcPort => proto.src_port = be_u16(payload),
+                        ProtoTupleAttr::CtaProtoDstPort => proto.dst_port = be_u16(payload),
+                        ProtoTupleAttr::CtaProtoIcmpId => proto.icmp_id = be_u16(payload),
+                        ProtoTupleAttr::CtaProtoIcmpType => {
+                            proto.icmp_type = payload.first().copied();
+                        }
+                        ProtoTupleAttr::CtaProtoIcmpCode => {
+                            proto.icmp_code = payload.first().copied();
+                        }
+                        ProtoTupleAttr::CtaProtoIcmpV6Id => proto.icmpv6_id = be_u16(payload),
+                        ProtoTupleAttr::CtaProtoIcmpV6Type => {
+                            proto.icmpv6_type = payload.first().copied();
+                        }
+                        ProtoTupleAttr::CtaProtoIcmpV6Code => {
+                            proto.icmpv6_code = payload.first().copied();
+                        }
+                        _ => {}
+                    }
+                }
+                tuple.proto = Some(proto);
+            }
+            TupleAttr::CtaTupleZone => tuple.zone = be_u16(payload),
+            _ => {}
+        }
+    }
+
+    tuple
+}
+
+fn ipv4(payload: &[u8]) -> Option<IpAddr> {
+    let octets: [u8; 4] = payload.try_into().ok()?;
+    Some(IpAddr::V4(Ipv4Addr::from(octets)))
+}
+
+fn ipv6(payload: &[u8]) -> Option<IpAddr> {
+    let octets: [u8; 16] = payload.try_into().ok()?;
+    Some(IpAddr::V6(Ipv6Addr::from(octets)))
+}
+
+fn be_u16(payload: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(payload.try_into().ok()?))
+}
+
+fn be_u32(payload: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(payload.try_into().ok()?))
+}